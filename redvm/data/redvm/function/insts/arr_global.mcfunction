@@ -0,0 +1 @@
+$data modify storage redvm arrays.g$(_) set value $(value)
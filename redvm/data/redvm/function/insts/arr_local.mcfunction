@@ -0,0 +1,4 @@
+$execute store result storage redvm args.a int 1 run scoreboard players add sp2 redvm.regs $(_)
+$scoreboard players remove sp2 redvm.regs $(_)
+$data modify storage redvm args.value set value $(value)
+function redvm:utils/arr_local with storage redvm args
@@ -1,3 +1,4 @@
+execute if score sp redvm.regs >= max_sp redvm.regs run return run function redvm:utils/error {msg:"stack overflow"}
 execute store result storage redvm args.0 int 1 run scoreboard players get sp redvm.regs
 $data modify storage redvm args.1 set value $(_)
 function redvm:utils/const with storage redvm args
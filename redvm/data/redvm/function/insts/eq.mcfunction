@@ -1,3 +1,4 @@
+execute if score sp redvm.regs matches ..1 run return run function redvm:utils/error {msg:"stack underflow"}
 execute store result storage redvm args.1 int 1 run scoreboard players remove sp redvm.regs 1
 execute store result storage redvm args.0 int 1 run scoreboard players remove sp redvm.regs 1
 function redvm:utils/eq with storage redvm args
@@ -0,0 +1,8 @@
+execute if score sp redvm.regs matches ..0 run return run function redvm:utils/error {msg:"stack underflow"}
+execute store result storage redvm args.0 int 1 run scoreboard players remove sp redvm.regs 1
+$data modify storage redvm args.len set value $(len)
+execute store result score ok redvm.regs run function redvm:utils/index with storage redvm args
+execute if score ok redvm.regs matches 0 run return run function redvm:utils/bounds_error with storage redvm args
+$data modify storage redvm args.a set value $(_)
+function redvm:utils/get_elem_global with storage redvm args
+scoreboard players add sp redvm.regs 1
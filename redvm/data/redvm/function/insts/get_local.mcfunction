@@ -1,3 +1,4 @@
+execute if score sp redvm.regs >= max_sp redvm.regs run return run function redvm:utils/error {msg:"stack overflow"}
 $scoreboard players add sp2 redvm.regs $(_)
 execute store result storage redvm args.1 int 1 run scoreboard players get sp2 redvm.regs
 execute store result storage redvm args.0 int 1 run scoreboard players get sp redvm.regs
@@ -0,0 +1,6 @@
+execute if score sp redvm.regs >= max_sp redvm.regs run return run function redvm:utils/error {msg:"stack overflow"}
+$execute store result storage redvm args.1 int 1 run scoreboard players add sp2 redvm.regs $(_)
+$scoreboard players remove sp2 redvm.regs $(_)
+execute store result storage redvm args.0 int 1 run scoreboard players get sp redvm.regs
+function redvm:utils/set_str_local with storage redvm args
+scoreboard players add sp redvm.regs 1
@@ -0,0 +1,4 @@
+$execute store result storage redvm args.a int 1 run scoreboard players add sp2 redvm.regs $(_)
+$scoreboard players remove sp2 redvm.regs $(_)
+$data modify storage redvm args.arg set value $(arg)
+function redvm:utils/load_str_local with storage redvm args
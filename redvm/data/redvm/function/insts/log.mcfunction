@@ -1,4 +1,6 @@
-$execute store result storage redvm args.0 int 1 run scoreboard players add sp2 redvm.regs $(_)
-function redvm:utils/log_get with storage redvm args
+execute if score sp redvm.regs matches ..0 run return run function redvm:utils/error {msg:"stack underflow"}
+execute store result storage redvm args.0 int 1 run scoreboard players remove sp redvm.regs 1
+$data modify storage redvm args.level set value "$(_)"
+$data modify storage redvm args.color set value "$(color)"
+data modify storage redvm args.target set from storage redvm log_target
 function redvm:utils/log with storage redvm args
-$scoreboard players remove sp2 redvm.regs $(_)
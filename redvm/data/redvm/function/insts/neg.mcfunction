@@ -0,0 +1,4 @@
+execute if score sp redvm.regs matches ..0 run return run function redvm:utils/error {msg:"stack underflow"}
+execute store result storage redvm args.0 int 1 run scoreboard players remove sp redvm.regs 1
+function redvm:utils/neg with storage redvm args
+scoreboard players add sp redvm.regs 1
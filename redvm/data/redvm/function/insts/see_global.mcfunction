@@ -0,0 +1 @@
+$return run scoreboard players get $(_) redvm.global
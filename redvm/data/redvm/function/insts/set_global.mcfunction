@@ -0,0 +1,4 @@
+execute if score sp redvm.regs matches ..0 run return run function redvm:utils/error {msg:"stack underflow"}
+$data modify storage redvm args.0 set value $(_)
+execute store result storage redvm args.1 int 1 run scoreboard players remove sp redvm.regs 1
+function redvm:utils/set_global with storage redvm args
@@ -0,0 +1,5 @@
+execute if score sp redvm.regs >= max_sp redvm.regs run return run function redvm:utils/error {msg:"stack overflow"}
+execute store result storage redvm args.0 int 1 run scoreboard players get sp redvm.regs
+$data modify storage redvm args.text set value "$(_)"
+function redvm:utils/str_const with storage redvm args
+scoreboard players add sp redvm.regs 1
@@ -0,0 +1,7 @@
+execute if score running redvm.regs matches 1 run return fail
+execute unless data storage redvm insts[0] run return fail
+$scoreboard players set ip redvm.regs $(ip)
+scoreboard players set sp redvm.regs 0
+scoreboard players set sp2 redvm.regs 0
+scoreboard players set sleep redvm.regs 0
+scoreboard players set running redvm.regs 1
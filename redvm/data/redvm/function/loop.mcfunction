@@ -1,7 +1,7 @@
 execute store result storage redvm args.0 int 1 run scoreboard players get ip redvm.regs
 function redvm:utils/get_inst with storage redvm args
-execute unless data storage redvm args.0 run return 1
+execute unless data storage redvm args.0 run return run scoreboard players set running redvm.regs 0
 scoreboard players add ip redvm.regs 1
 function redvm:utils/run_cmd with storage redvm args
-
-schedule function redvm:loop 0.5t
+scoreboard players remove budget redvm.regs 1
+execute if score budget redvm.regs matches 1.. if score running redvm.regs matches 1 run function redvm:loop
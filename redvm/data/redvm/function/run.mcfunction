@@ -1,11 +1,19 @@
 data remove storage redvm insts
 scoreboard players reset * redvm.local
+scoreboard players reset * redvm.global
+data remove storage redvm arrays
+data remove storage redvm strings
 scoreboard objectives add redvm.local dummy
+scoreboard objectives add redvm.global dummy
 scoreboard objectives add redvm.regs dummy
 scoreboard players set sp redvm.regs 0
 scoreboard players set sp2 redvm.regs 0
 scoreboard players set ip redvm.regs 0
+execute unless score max_sp redvm.regs matches 1.. run scoreboard players set max_sp redvm.regs 1024
+execute unless score ipt redvm.regs matches 1.. run scoreboard players set ipt redvm.regs 256
+execute unless data storage redvm log_target run data modify storage redvm log_target set value "@a"
+scoreboard players set sleep redvm.regs 0
 
 $$(load_program)
 
-function redvm:loop
+scoreboard players set running redvm.regs 1
@@ -0,0 +1,5 @@
+execute unless score running redvm.regs matches 1 run return 0
+execute if score sleep redvm.regs matches 1.. run scoreboard players remove sleep redvm.regs 1
+execute if score sleep redvm.regs matches 1.. run return 0
+scoreboard players operation budget redvm.regs = ipt redvm.regs
+function redvm:loop
@@ -0,0 +1 @@
+$data modify storage redvm arrays.l$(a) set value $(value)
@@ -0,0 +1,3 @@
+scoreboard players set neg redvm.regs -1
+$scoreboard players operation $(0) redvm.local *= neg redvm.regs
+$scoreboard players remove $(0) redvm.local 1
@@ -0,0 +1 @@
+$function redvm:utils/error {msg:"index $(i) is out of bounds for length $(len)"}
@@ -0,0 +1,4 @@
+scoreboard players remove ip redvm.regs 1
+scoreboard players set running redvm.regs 0
+$tellraw @a [{"text":"redvm: $(msg) at ip ","color":"red"},{"score":{"name":"ip","objective":"redvm.regs"},"color":"red"}]
+data remove storage redvm insts
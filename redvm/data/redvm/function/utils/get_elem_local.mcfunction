@@ -0,0 +1 @@
+$execute store result score $(0) redvm.local run data get storage redvm arrays.l$(a)[$(i)]
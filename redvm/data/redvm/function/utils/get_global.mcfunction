@@ -0,0 +1 @@
+$scoreboard players operation $(0) redvm.local = $(1) redvm.global
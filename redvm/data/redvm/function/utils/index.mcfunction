@@ -0,0 +1,6 @@
+$execute store result score idx redvm.regs run scoreboard players get $(0) redvm.local
+execute store result storage redvm args.i int 1 run scoreboard players get idx redvm.regs
+$scoreboard players set len redvm.regs $(len)
+execute unless score idx redvm.regs matches 0.. run return 0
+execute if score idx redvm.regs < len redvm.regs run return 1
+return 0
@@ -0,0 +1 @@
+$data modify storage redvm eargs.$(arg) set from storage redvm strings.l$(a)
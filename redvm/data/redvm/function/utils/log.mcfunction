@@ -1 +1 @@
-$say $(0)
+$tellraw $(target) [{"text":"[$(level)] ","color":"$(color)"},{"nbt":"strings.l$(0)","storage":"redvm"}]
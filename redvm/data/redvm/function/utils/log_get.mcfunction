@@ -1 +0,0 @@
-$execute store result storage redvm args.0 int 1 run scoreboard players get $(0) redvm.local
@@ -0,0 +1,2 @@
+scoreboard players set neg redvm.regs -1
+$scoreboard players operation $(0) redvm.local *= neg redvm.regs
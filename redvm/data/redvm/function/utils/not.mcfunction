@@ -0,0 +1 @@
+$execute store result score $(0) redvm.local if score $(0) redvm.local matches ..0
@@ -0,0 +1 @@
+$scoreboard players operation $(0) redvm.global $(op) $(1) redvm.local
@@ -0,0 +1 @@
+$scoreboard players operation $(0) redvm.local $(op) $(1) redvm.local
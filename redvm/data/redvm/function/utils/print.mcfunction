@@ -0,0 +1 @@
+$tellraw @a {"nbt":"strings.l$(0)","storage":"redvm"}
@@ -0,0 +1 @@
+$execute store result storage redvm arrays.l$(a)[$(i)] int 1 run scoreboard players get $(1) redvm.local
@@ -0,0 +1 @@
+$scoreboard players operation sleep redvm.regs = $(0) redvm.local
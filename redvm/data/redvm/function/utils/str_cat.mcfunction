@@ -0,0 +1,3 @@
+$data modify storage redvm args.a set from storage redvm strings.l$(0)
+$data modify storage redvm args.b set from storage redvm strings.l$(1)
+function redvm:utils/str_cat_set with storage redvm args
@@ -0,0 +1 @@
+$data modify storage redvm strings.l$(0) set from storage redvm args.text
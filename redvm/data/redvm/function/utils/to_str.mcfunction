@@ -0,0 +1,2 @@
+$execute store result storage redvm args.n int 1 run scoreboard players get $(0) redvm.local
+function redvm:utils/to_str_set with storage redvm args
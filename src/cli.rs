@@ -0,0 +1,191 @@
+use crate::{datapack, parser::LogLevel, vm};
+
+pub const USAGE: &str = "\
+Usage: project-mcs <command> [options] <file>
+
+Commands:
+    build      Compile the program into a datapack
+    check      Only run the semantic analysis and report diagnostics
+    dump-ast   Print the syntax tree
+    dump-ir    Print the redvm intermediate representation
+    run        Execute the program in the headless redvm interpreter
+               (accepts both luba sources and compiled `.mcfunction` files)
+
+Options:
+    -o, --output <path>    Output datapack directory, `.zip` archive or, with `--redvm`,
+                           a bare `.mcfunction` program (default: `out`)
+    --namespace <name>     Datapack namespace (default: `luba`)
+    --pack-format <n>      Datapack pack format (default: 48)
+    --max-stack <n>        Maximum stack depth of redvm (default: 1024)
+    --insts-per-tick <n>   Instructions redvm executes per game tick (default: 256)
+    --log-level <level>    Lowest log level that is compiled in: `debug`, `info`, `warn`,
+                           `error` or `off` (default: `debug`)
+    --log-target <target>  Selector of the players that see the logs (default: `@a`)
+    --redvm                Compile for the redvm virtual machine
+    --extern <name>=<n>    Value returned by an extern function in `run` (default: 0)
+    --ticks <n>            Game ticks `run` simulates for `#[tick]` functions (default: 20)
+    --max-steps <n>        Instructions `run` executes before it halts (default: 10000000)
+    -h, --help             Print this message";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    Build,
+    Check,
+    DumpAst,
+    DumpIr,
+    Run,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub command:   Command,
+    pub file_path: String,
+    pub output:    Option<String>,
+    pub redvm:     bool,
+    pub externs:   Vec<(String, i32)>,
+    pub ticks:     i32,
+    pub max_steps: u64,
+    pub pack:      datapack::Options,
+}
+
+fn is_valid_namespace(ns: &str) -> bool {
+    !ns.is_empty() && ns.bytes().all(|c| matches!(c, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.'))
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+
+    let command = match args.next().as_deref() {
+        Some("build")    => Command::Build,
+        Some("check")    => Command::Check,
+        Some("dump-ast") => Command::DumpAst,
+        Some("dump-ir")  => Command::DumpIr,
+        Some("run")      => Command::Run,
+        Some("-h" | "--help" | "help") => Command::Help,
+        Some(command)    => return Err(format!("Unknown command `{command}`")),
+        None             => return Err("Command must be provided".to_string()),
+    };
+
+    let mut file_path = None;
+    let mut output = None;
+    let mut redvm = false;
+    let mut externs = Vec::new();
+    let mut ticks = vm::DEFAULT_TICKS;
+    let mut max_steps = vm::DEFAULT_MAX_STEPS;
+    let mut pack = datapack::Options::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Option `{name}` requires a value"));
+
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(value(&arg)?),
+            "--namespace" => {
+                let ns = value(&arg)?;
+                if !is_valid_namespace(&ns) {
+                    return Err(format!("Invalid namespace `{ns}`: only `a-z`, `0-9`, `_`, `-` and `.` are allowed"));
+                }
+                pack.namespace = ns;
+            },
+            "--pack-format" => {
+                let n = value(&arg)?;
+                pack.pack_format = n.parse().map_err(|_| format!("Invalid pack format `{n}`"))?;
+            },
+            "--max-stack" => {
+                let n = value(&arg)?;
+                pack.max_stack = n.parse().ok().filter(|n| *n > 0).ok_or_else(|| format!("Invalid stack depth `{n}`"))?;
+            },
+            "--insts-per-tick" => {
+                let n = value(&arg)?;
+                pack.insts_per_tick = n.parse().ok().filter(|n| *n > 0).ok_or_else(|| format!("Invalid instruction count `{n}`"))?;
+            },
+            "--log-level" => {
+                let level = value(&arg)?;
+                pack.log_level = match level.as_str() {
+                    "off" => None,
+                    _ => Some(LogLevel::from_name(&level).ok_or_else(|| format!("Invalid log level `{level}`"))?),
+                };
+            },
+            "--log-target" => pack.log_target = value(&arg)?,
+            "--redvm" => redvm = true,
+            "--extern" => {
+                let value = value(&arg)?;
+                let result = value.split_once('=').and_then(|(name, n)| Some((name.to_string(), n.parse().ok()?)));
+                externs.push(result.ok_or_else(|| format!("Invalid extern result `{value}`, expected `<name>=<n>`"))?);
+            },
+            "--ticks" => {
+                let n = value(&arg)?;
+                ticks = n.parse().ok().filter(|n| *n > 0).ok_or_else(|| format!("Invalid tick count `{n}`"))?;
+            },
+            "--max-steps" => {
+                let n = value(&arg)?;
+                max_steps = n.parse().ok().filter(|n| *n > 0).ok_or_else(|| format!("Invalid step count `{n}`"))?;
+            },
+            "-h" | "--help" => return parse(["help".to_string()]),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option `{arg}`")),
+            _ if file_path.is_some() => return Err(format!("Unexpected argument `{arg}`")),
+            _ => file_path = Some(arg),
+        }
+    }
+
+    let file_path = match (command, file_path) {
+        (Command::Help, _) => String::new(),
+        (_, Some(file_path)) => file_path,
+        (_, None) => return Err("Source file must be provided".to_string()),
+    };
+
+    Ok(Args { command, file_path, output, redvm, externs, ticks, max_steps, pack })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Result<Args, String> {
+        parse(s.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn build_options() {
+        let args = args("build main.luba -o dist/pack --namespace my_pack --pack-format 57").unwrap();
+        assert_eq!(args.command, Command::Build);
+        assert_eq!(args.file_path, "main.luba");
+        assert_eq!(args.output.as_deref(), Some("dist/pack"));
+        assert_eq!(args.pack.namespace, "my_pack");
+        assert_eq!(args.pack.pack_format, 57);
+        assert!(!args.redvm);
+
+        let args = super::parse(["run", "a.luba", "--extern", "getblock=-3"].map(str::to_string)).unwrap();
+        assert_eq!(args.externs, [("getblock".to_string(), -3)]);
+        assert_eq!(args.ticks, 20);
+        assert_eq!(self::args("run a.luba --ticks 100").unwrap().ticks, 100);
+        assert_eq!(self::args("run a.luba --max-steps 5000").unwrap().max_steps, 5000);
+    }
+
+    #[test]
+    fn log_options() {
+        let args = args("build a.luba --log-level warn --log-target @p[tag=dev]").unwrap();
+        assert_eq!(args.pack.log_level, Some(LogLevel::Warn));
+        assert_eq!(args.pack.log_target, "@p[tag=dev]");
+        assert_eq!(super::parse(["run", "a.luba", "--log-level", "off"].map(str::to_string)).unwrap().pack.log_level, None);
+        assert_eq!(super::parse(["run", "a.luba"].map(str::to_string)).unwrap().pack.log_level, Some(LogLevel::Debug));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(args("").unwrap_err(), "Command must be provided");
+        assert_eq!(args("compile a.luba").unwrap_err(), "Unknown command `compile`");
+        assert_eq!(args("check").unwrap_err(), "Source file must be provided");
+        assert_eq!(args("build a.luba -o").unwrap_err(), "Option `-o` requires a value");
+        assert_eq!(args("build a.luba --pack-format x").unwrap_err(), "Invalid pack format `x`");
+        assert_eq!(args("run a.luba --max-stack 0").unwrap_err(), "Invalid stack depth `0`");
+        assert_eq!(args("run a.luba --insts-per-tick -1").unwrap_err(), "Invalid instruction count `-1`");
+        assert_eq!(args("run a.luba --ticks 0").unwrap_err(), "Invalid tick count `0`");
+        assert_eq!(args("run a.luba --max-steps -1").unwrap_err(), "Invalid step count `-1`");
+        assert!(args("build a.luba --namespace Pack").is_err());
+        assert_eq!(args("build a.luba --log-level trace").unwrap_err(), "Invalid log level `trace`");
+        assert_eq!(args("run a.luba b.luba").unwrap_err(), "Unexpected argument `b.luba`");
+        assert_eq!(args("run a.luba --extern getblock").unwrap_err(), "Invalid extern result `getblock`, expected `<name>=<n>`");
+        assert_eq!(args("dump-ir --help").unwrap().command, Command::Help);
+    }
+}
@@ -1,155 +1,201 @@
-use std::{collections::HashMap, fs::File, io::{Seek, SeekFrom, Write}};
+use std::collections::HashMap;
 
-use crate::{compilation_err, exit_failure, lexer::BinOpKind, parser::{Ast, Block, ExprKind, Stmt, StmtKind}, semantic::{Name, Scope, ScopeIdx, Type, SP2}};
-
-type IP = usize;
-type FilePos = u64;
-type JmpLabel = usize;
+use crate::{ir::{Inst, Label, Program, Reg}, lexer::BinOpKind, parser::{cmd_parts, Ast, BinOpExpr, Block, CmdPart, DataType, Entry, Expr, ExprKind, LogLevel, Stmt, StmtKind}, semantic::{field_offset, size_of, slots, struct_info, FnDeclInfo, Name, Scope, ScopeIdx, Type, SP2}};
 
 struct Loop {
-    start: JmpLabel,
-    end:   JmpLabel,
-}
-
-struct CallLabelUsage<'a> {
-    pos:  FilePos,
-    name: &'a str,
-}
-
-struct JmpLabelUsage {
-    pos:   FilePos,
-    label: JmpLabel,
+    start: Label,
+    end:   Label,
 }
 
 struct Compiler<'a> {
-    call_labels: HashMap<&'a str, IP>,
-    call_label_usages: Vec<CallLabelUsage<'a>>,
-
-    jmp_labels: Vec<IP>,
-    jmp_label_usages: Vec<JmpLabelUsage>,
+    call_labels: HashMap<&'a str, Label>,
+    ret_label: Label,
+    // the type of the returned value of the current function
+    ret_type: Option<DataType>,
+    // logs below the level aren't compiled, `None` leaves out all of them
+    log_level: Option<LogLevel>,
 
     curr_scope_idx: ScopeIdx,
     scopes: Vec<Scope<'a>>,
-    file: File,
-    ip: IP,
-}
-
-macro_rules! write_ln {
-    ($file:expr, $($args:tt)*) => {
-        let _ = write!($file, $($args)*).unwrap_or_else(|err| {
-            compilation_err!("Could not write: {err}");
-        });
-    }
-}
-
-macro_rules! cmd {
-    ($comp:ident, $($arg:tt)*) => {
-        write_ln!($comp.file, "data modify storage redvm insts append value '");
-        write_ln!($comp.file, $($arg)*);
-        write_ln!($comp.file, "'\n");
-        $comp.ip += 1;
-    };
-}
-
-macro_rules! inst {
-    ($comp:ident, $($arg:tt)*) => {
-        write_ln!($comp.file, "data modify storage redvm insts append value 'function redvm:insts/");
-        write_ln!($comp.file, $($arg)*);
-        write_ln!($comp.file, "'\n");
-        $comp.ip += 1;
-    };
+    program: Program<'a>,
 }
 
 impl<'a> Compiler<'a> {
-    const RET_JMP_LABEL: usize = 0;
+    fn call_label(&mut self, name: &'a str) -> Label {
+        if let Some(label) = self.call_labels.get(name) {
+            return *label;
+        }
 
-    fn call_label(&mut self, name: &'a str) {
-        inst!(self, "call {{_:0000000000}}");
-        let pos = self.curr_pos()-13; // -13 to get the start position of addr
-        self.call_label_usages.push(CallLabelUsage { pos, name });
+        let label = self.program.new_label();
+        self.call_labels.insert(name, label);
+        label
     }
 
-    fn jmpif_label(&mut self, label: JmpLabel) {
-        inst!(self, "jmp_if {{_:0000000000}}");
-        let pos = self.curr_pos()-13; // -13 to get the start position of addr
-        self.jmp_label_usages.push(JmpLabelUsage { pos, label });
+    fn set_call_label(&mut self, name: &'a str) {
+        let label = self.call_label(name);
+        self.program.push(Inst::Fn(name));
+        self.program.push(Inst::Label(label));
     }
 
-    fn jmp_label(&mut self, label: JmpLabel) {
-        cmd!(self, "scoreboard players set ip redvm.regs 0000000000");
-        let pos = self.curr_pos()-12; // -12 to get the start position of addr
-        self.jmp_label_usages.push(JmpLabelUsage { pos, label });
-    }
+    fn compile_expr(&mut self, expr: &'a ExprKind, scope: ScopeIdx) {
+        match expr {
+            ExprKind::Num(n) => {
+                self.program.push(Inst::Const(*n));
+            },
 
-    fn new_jmp_label(&mut self) -> JmpLabel {
-        self.jmp_labels.push(0); // label is not init yet
-        self.jmp_labels.len()-1
-    }
+            ExprKind::Bool(b) => {
+                self.program.push(Inst::Const(*b as i32));
+            },
 
-    fn set_jmp_label(&mut self, label: JmpLabel) {
-        self.jmp_labels[label] = self.ip;
-    }
+            ExprKind::Str(text) => {
+                self.program.push(Inst::StrConst(text));
+            },
 
-    fn set_call_label(&mut self, label_name: &'a str) {
-        write_ln!(self.file, "\n# {label_name}\n"); // this is just a comment
-        self.call_labels.insert(label_name, self.ip);
-    }
+            ExprKind::Var(name) => {
+                self.get_var(name, scope);
+            },
 
-    fn write_call_labels(mut self) {
-        for i in 0..self.call_label_usages.len() {
-            self.seek(self.call_label_usages[i].pos);
-            let data = *self.call_labels.get(self.call_label_usages[i].name).unwrap();
-            write_ln!(self.file, "{data:0>10}");
-        }
-    }
+            ExprKind::Field(data) => {
+                let (offset, ty) = field_offset(&self.scopes, self.var_type(data.name, scope), &data.fields).unwrap();
+                self.push_slots(self.get_type_var(data.name, scope), offset, ty);
+            },
 
-    fn write_jmp_labels(&mut self) {
-        let currpos = self.curr_pos();
-        for i in 0..self.jmp_label_usages.len() {
-            self.seek(self.jmp_label_usages[i].pos);
-            let data = self.jmp_labels[self.jmp_label_usages[i].label];
-            assert_ne!(data, 0);
-            write_ln!(self.file, "{data:0>10}");
-        }
+            // fields are pushed in the order of the declaration
+            ExprKind::Struct(data) => {
+                let info = struct_info(&self.scopes, data.name).unwrap().clone();
+                for field in &info.fields {
+                    let (_, value) = data.fields.iter().find(|(name, _)| *name == field.name).unwrap();
+                    self.compile_expr(&value.kind, scope);
+                }
+            },
 
-        self.jmp_labels.clear();
-        self.jmp_label_usages.clear();
-        self.seek(currpos);
-    }
+            ExprKind::Index(data) => {
+                self.compile_expr(&data.index.kind, scope);
+                let len = self.array_len(data.name, scope);
+                match self.get_type_var(data.name, scope) {
+                    (idx, true)  => self.program.push(Inst::GetElemGlobal(idx, len)),
+                    (idx, false) => self.program.push(Inst::GetElemLocal(idx, len)),
+                }
+            },
 
-    fn compile_expr(&mut self, expr: &ExprKind, scope: ScopeIdx) {
-        match expr {
-            ExprKind::Num(n) => {
-                inst!(self, "const {{_:{n}}}");
+            ExprKind::Array(_) => unreachable!("array literals are values of variables"),
+
+            ExprKind::BinOp(data) if matches!(data.op, BinOpKind::And | BinOpKind::Or) => {
+                self.compile_logical(data, scope);
             },
 
-            ExprKind::Var(name) => {
-                inst!(
-                    self, "get_local {{_:{}}}",
-                    self.get_type_var(name, scope)
-                );
+            ExprKind::BinOp(data) if data.op == BinOpKind::Add && self.is_str(expr, scope) => {
+                self.compile_text(&data.lhs.kind, scope);
+                self.compile_text(&data.rhs.kind, scope);
+                self.program.push(Inst::StrCat);
             },
 
             ExprKind::BinOp(data) => {
                 self.compile_expr(&data.lhs.kind, scope);
                 self.compile_expr(&data.rhs.kind, scope);
-                inst!(self, "{}", Self::binop_to_inst(data.op.clone()));
+                self.program.push(Inst::BinOp(data.op.clone()));
             },
 
+            ExprKind::UnaryOp(data) => {
+                self.compile_expr(&data.expr.kind, scope);
+                self.program.push(Inst::UnaryOp(data.op.clone()));
+            },
+
+            // the returned value is left in the reserved slots
             ExprKind::FnCall(data) => {
-                cmd!(self, "scoreboard players add sp redvm.regs 1");
+                let (result_size, params_size) = self.frame_sizes(data.name);
+                self.program.push(Inst::RegAdd(Reg::Sp, result_size));
                 for arg in &data.args {
                     self.compile_expr(&arg.kind, scope);
                 }
-                self.call_label(data.name);
-                cmd!(self, "scoreboard players remove sp redvm.regs {}", data.args.len());
+                let label = self.call_label(data.name);
+                self.program.push(Inst::Call(label));
+                self.program.push(Inst::RegSub(Reg::Sp, params_size));
+            },
+        }
+    }
+
+    // ints are converted to their text
+    fn compile_text(&mut self, expr: &'a ExprKind, scope: ScopeIdx) {
+        self.compile_expr(expr, scope);
+        if !self.is_str(expr, scope) {
+            self.program.push(Inst::ToStr);
+        }
+    }
+
+    // `"x={} y={}"` is the concatenation of the text parts and the values
+    fn compile_format(&mut self, format: &'a str, args: &'a [Expr], scope: ScopeIdx) {
+        let mut pushed = false;
+        let mut args = args.iter();
+        for (i, text) in format.split("{}").enumerate() {
+            if i > 0 {
+                self.compile_text(&args.next().unwrap().kind, scope);
+                self.join(&mut pushed);
+            }
+            if !text.is_empty() {
+                self.program.push(Inst::StrConst(text));
+                self.join(&mut pushed);
+            }
+        }
+        if !pushed {
+            self.program.push(Inst::StrConst(""));
+        }
+    }
+
+    fn join(&mut self, pushed: &mut bool) {
+        if *pushed {
+            self.program.push(Inst::StrCat);
+        }
+        *pushed = true;
+    }
+
+    fn is_str(&self, expr: &ExprKind, scope: ScopeIdx) -> bool {
+        match expr {
+            ExprKind::Str(_) => true,
+            ExprKind::Var(name) => self.var_type(name, scope) == DataType::Str,
+            ExprKind::Field(data) => {
+                field_offset(&self.scopes, self.var_type(data.name, scope), &data.fields).unwrap().1 == DataType::Str
             },
+            ExprKind::FnCall(data) => self.fn_info(data.name).result == Some(DataType::Str),
+            ExprKind::BinOp(data) => {
+                data.op == BinOpKind::Add && (self.is_str(&data.lhs.kind, scope) || self.is_str(&data.rhs.kind, scope))
+            },
+            _ => false,
         }
     }
 
-    fn get_type_var(&self, name: Name, scope: ScopeIdx) -> SP2 {
-        if let Some(Type::Var(sp2)) = self.scopes[scope].items.get(name) {
-            *sp2
+    // the right operand is evaluated only when it decides the result,
+    // which is pushed as 0 or 1
+    fn compile_logical(&mut self, data: &'a BinOpExpr, scope: ScopeIdx) {
+        let true_label  = self.program.new_label();
+        let false_label = self.program.new_label();
+        let end_label   = self.program.new_label();
+
+        self.compile_expr(&data.lhs.kind, scope);
+        if data.op == BinOpKind::And {
+            let rhs_label = self.program.new_label();
+            self.program.push(Inst::JmpIf(rhs_label));
+            self.program.push(Inst::Jmp(false_label));
+            self.program.push(Inst::Label(rhs_label));
+        } else {
+            self.program.push(Inst::JmpIf(true_label));
+        }
+
+        self.compile_expr(&data.rhs.kind, scope);
+        self.program.push(Inst::JmpIf(true_label));
+
+        self.program.push(Inst::Label(false_label));
+        self.program.push(Inst::Const(0));
+        self.program.push(Inst::Jmp(end_label));
+        self.program.push(Inst::Label(true_label));
+        self.program.push(Inst::Const(1));
+        self.program.push(Inst::Label(end_label));
+    }
+
+    // `(slot, is_global)`, variables of the global scope are globals
+    fn get_type_var(&self, name: Name, scope: ScopeIdx) -> (SP2, bool) {
+        if let Some(Type::Var(idx, _)) = self.scopes[scope].items.get(name) {
+            (*idx, scope == 0)
         } else if scope != 0 {
             self.get_type_var(name, self.scopes[scope].parent)
         } else {
@@ -157,46 +203,211 @@ impl<'a> Compiler<'a> {
         }
     }
 
-    fn compile_stmt(&mut self, stmt: &Stmt<'a>, scope: ScopeIdx, lup: &Loop) {
+    fn var_type(&self, name: Name, scope: ScopeIdx) -> DataType {
+        match self.scopes[scope].items.get(name) {
+            Some(Type::Var(_, ty)) => *ty,
+            Some(_) => unreachable!("{name}"),
+            None => self.var_type(name, self.scopes[scope].parent),
+        }
+    }
+
+    fn array_len(&self, name: Name, scope: ScopeIdx) -> usize {
+        match self.var_type(name, scope) {
+            DataType::Array(_, len) => len,
+            _ => unreachable!("{name}"),
+        }
+    }
+
+    // `pos.x` of `@cmd` as the slot of the field and its type
+    fn var_path(&self, path: Name, scope: ScopeIdx) -> ((SP2, bool), DataType) {
+        let mut fields: Vec<&str> = path.split('.').collect();
+        let name = fields.remove(0);
+        let (offset, ty) = field_offset(&self.scopes, self.var_type(name, scope), &fields).unwrap();
+        let (idx, is_global) = self.get_type_var(name, scope);
+        ((idx + offset, is_global), ty)
+    }
+
+    fn fn_info(&self, name: Name) -> &FnDeclInfo {
+        match self.scopes[0].items.get(name) {
+            Some(Type::FnDecl(info)) => info,
+            _ => unreachable!("{name}"),
+        }
+    }
+
+    // `(result, params)` in slots
+    fn frame_sizes(&self, name: Name) -> (usize, usize) {
+        let info = self.fn_info(name);
+        let result = info.result.map_or(0, |ty| size_of(&self.scopes, ty));
+        (result, info.params.iter().map(|ty| size_of(&self.scopes, *ty)).sum())
+    }
+
+    fn push_slots(&mut self, var: (SP2, bool), offset: usize, ty: DataType) {
+        for (i, slot) in slots(&self.scopes, ty).into_iter().enumerate() {
+            let idx = var.0 + offset + i;
+            self.program.push(match (slot, var.1) {
+                (DataType::Str, true)  => Inst::GetStrGlobal(idx),
+                (DataType::Str, false) => Inst::GetStrLocal(idx),
+                (_, true)  => Inst::GetGlobal(idx),
+                (_, false) => Inst::GetLocal(idx),
+            });
+        }
+    }
+
+    // the last slot is on the top of the stack
+    fn pop_slots(&mut self, var: (SP2, bool), offset: usize, ty: DataType) {
+        for (i, slot) in slots(&self.scopes, ty).into_iter().enumerate().rev() {
+            let idx = var.0 + offset + i;
+            self.program.push(match (slot, var.1) {
+                (DataType::Str, true)  => Inst::SetStrGlobal(idx),
+                (DataType::Str, false) => Inst::SetStrLocal(idx),
+                (_, true)  => Inst::SetGlobal(idx),
+                (_, false) => Inst::SetLocal(idx),
+            });
+        }
+    }
+
+    // the list is created with the constant elements, the rest are set one by one
+    fn init_array(&mut self, name: Name, elems: &'a [Expr], scope: ScopeIdx) {
+        let value = elems.iter().map(|e| match e.kind {
+            ExprKind::Num(n)  => n,
+            ExprKind::Bool(b) => b as i32,
+            _ => 0,
+        }).collect();
+        let var = self.get_type_var(name, scope);
+        match var {
+            (idx, true)  => self.program.push(Inst::ArrGlobal(idx, value)),
+            (idx, false) => self.program.push(Inst::ArrLocal(idx, value)),
+        }
+
+        for (i, elem) in elems.iter().enumerate() {
+            if matches!(elem.kind, ExprKind::Num(_) | ExprKind::Bool(_)) { continue; }
+            self.program.push(Inst::Const(i as i32));
+            self.compile_expr(&elem.kind, scope);
+            self.set_elem(var, elems.len());
+        }
+    }
+
+    fn set_elem(&mut self, var: (SP2, bool), len: usize) {
+        match var {
+            (idx, true)  => self.program.push(Inst::SetElemGlobal(idx, len)),
+            (idx, false) => self.program.push(Inst::SetElemLocal(idx, len)),
+        }
+    }
+
+    fn get_var(&mut self, name: Name, scope: ScopeIdx) {
+        self.push_slots(self.get_type_var(name, scope), 0, self.var_type(name, scope));
+    }
+
+    fn set_var(&mut self, name: Name, scope: ScopeIdx) {
+        self.pop_slots(self.get_type_var(name, scope), 0, self.var_type(name, scope));
+    }
+
+    fn compile_stmt(&mut self, stmt: &'a Stmt<'a>, scope: ScopeIdx, lup: &Loop) {
         match &stmt.kind {
-            StmtKind::VarDecl(_) => {},
-            StmtKind::VarAssign { name, expr } => {
+            StmtKind::VarDecl(name, DataType::Array(_, len)) => {
+                match self.get_type_var(name, scope) {
+                    (idx, true)  => self.program.push(Inst::ArrGlobal(idx, vec![0; *len])),
+                    (idx, false) => self.program.push(Inst::ArrLocal(idx, vec![0; *len])),
+                }
+            },
+
+            // scores of the globals don't exist until they are set
+            StmtKind::VarDecl(name, ty) if scope == 0 => {
+                for slot in slots(&self.scopes, *ty) {
+                    self.program.push(if slot == DataType::Str { Inst::StrConst("") } else { Inst::Const(0) });
+                }
+                self.set_var(name, scope);
+            },
+
+            StmtKind::VarDecl(..) | StmtKind::ConstDecl { .. } | StmtKind::StructDecl { .. } | StmtKind::Import(_) => {},
+            StmtKind::VarAssign { name, expr: Expr { kind: ExprKind::Array(elems), .. } }
+            | StmtKind::VarDeclAssign { name, expr: Expr { kind: ExprKind::Array(elems), .. }, .. } => {
+                self.init_array(name, elems, scope);
+            },
+
+            StmtKind::FieldAssign { name, fields, expr } => {
+                self.compile_expr(&expr.kind, scope);
+                let (offset, ty) = field_offset(&self.scopes, self.var_type(name, scope), fields).unwrap();
+                self.pop_slots(self.get_type_var(name, scope), offset, ty);
+            },
+
+            StmtKind::IndexAssign { name, index, expr } => {
+                self.compile_expr(&index.kind, scope);
+                self.compile_expr(&expr.kind, scope);
+                let len = self.array_len(name, scope);
+                self.set_elem(self.get_type_var(name, scope), len);
+            },
+
+            StmtKind::VarAssign { name, expr } | StmtKind::VarDeclAssign { name, expr, .. } => {
                 self.compile_expr(&expr.kind, scope);
-                let local_idx = self.get_type_var(name, scope);
-                inst!(self, "set_local {{_:{local_idx}}}");
+                self.set_var(name, scope);
+            },
+
+            StmtKind::CompoundAssign { name, expr, .. } if self.var_type(name, scope) == DataType::Str => {
+                self.get_var(name, scope);
+                self.compile_text(&expr.kind, scope);
+                self.program.push(Inst::StrCat);
+                self.set_var(name, scope);
             },
 
-            StmtKind::VarDeclAssign { name, expr } => {
+            // the value is combined with the variable in place
+            StmtKind::CompoundAssign { name, op, expr } => {
                 self.compile_expr(&expr.kind, scope);
-                let local_idx = self.get_type_var(name, scope);
-                inst!(self, "set_local {{_:{local_idx}}}");
+                match self.get_type_var(name, scope) {
+                    (idx, true)  => self.program.push(Inst::OpGlobal(idx, op.clone())),
+                    (idx, false) => self.program.push(Inst::OpLocal(idx, op.clone())),
+                }
             },
 
             StmtKind::ReturnVal(expr) => {
                 self.compile_expr(&expr.kind, scope);
-                inst!(self, "set_local {{_:0}}");
-                self.jmp_label(Self::RET_JMP_LABEL);
+                self.pop_slots((0, false), 0, self.ret_type.unwrap());
+                self.program.push(Inst::Jmp(self.ret_label));
             },
 
             StmtKind::Return => {
-                self.jmp_label(Self::RET_JMP_LABEL);
+                self.program.push(Inst::Jmp(self.ret_label));
             },
 
             StmtKind::FnCall { name, args } => {
-                cmd!(self, "scoreboard players add sp redvm.regs 1");
+                let (result_size, params_size) = self.frame_sizes(name);
+                let reserved = result_size.max(1);
+                self.program.push(Inst::RegAdd(Reg::Sp, reserved));
                 for arg in args { self.compile_expr(&arg.kind, scope); }
-                self.call_label(name);
-                cmd!(self, "scoreboard players remove sp redvm.regs {}", args.len()+1);
+                let label = self.call_label(name);
+                self.program.push(Inst::Call(label));
+                self.program.push(Inst::RegSub(Reg::Sp, params_size+reserved));
             },
 
             StmtKind::BuilinFnCall { arg, name } => {
                 match *name {
-                    "log" => {
-                        inst!(self, "log {{_:{}}}", self.get_type_var(arg, scope));
-                    },
-
                     "cmd" => {
-                        cmd!(self, "{arg}");
+                        let parts = cmd_parts(arg);
+                        if !parts.iter().any(|part| matches!(part, CmdPart::Var(_))) {
+                            self.program.push(Inst::Cmd(arg));
+                            return;
+                        }
+
+                        // variables are passed to the macro function as `$(0)`, `$(1)`, ...
+                        let mut body = String::from("$");
+                        let mut arg_count = 0;
+                        for part in parts {
+                            match part {
+                                CmdPart::Text(text) => body.push_str(text),
+                                CmdPart::Var(var) => {
+                                    self.program.push(match self.var_path(var, scope) {
+                                        ((idx, true), DataType::Str)  => Inst::LoadStrGlobal(idx, arg_count),
+                                        ((idx, false), DataType::Str) => Inst::LoadStrArg(idx, arg_count),
+                                        ((idx, true), _)  => Inst::LoadMacroGlobal(idx, arg_count),
+                                        ((idx, false), _) => Inst::LoadMacroArg(idx, arg_count),
+                                    });
+                                    body += &format!("$({arg_count})");
+                                    arg_count += 1;
+                                },
+                            }
+                        }
+                        let idx = self.program.new_macro(body);
+                        self.program.push(Inst::CallMacro(idx));
                     },
 
                     _ => unreachable!()
@@ -204,187 +415,299 @@ impl<'a> Compiler<'a> {
             },
 
             StmtKind::If { cond, then, elzeifs, elze } => {
-                let mut then_label = self.new_jmp_label();
-                let mut else_label = self.new_jmp_label();
-                let end_label      = self.new_jmp_label();
+                let mut then_label = self.program.new_label();
+                let mut else_label = self.program.new_label();
+                let end_label      = self.program.new_label();
 
                 self.compile_expr(&cond.kind, scope);
-                self.jmpif_label(then_label);
-                self.jmp_label(else_label);
-                self.set_jmp_label(then_label);
+                self.program.push(Inst::JmpIf(then_label));
+                self.program.push(Inst::Jmp(else_label));
+                self.program.push(Inst::Label(then_label));
 
                 self.compile_block(then, lup);
 
-                self.jmp_label(end_label);
+                self.program.push(Inst::Jmp(end_label));
 
                 for elzeif in elzeifs {
-                    self.set_jmp_label(else_label);
+                    self.program.push(Inst::Label(else_label));
 
-                    then_label = self.new_jmp_label();
-                    else_label = self.new_jmp_label();
+                    then_label = self.program.new_label();
+                    else_label = self.program.new_label();
 
                     self.compile_expr(&elzeif.cond.kind, scope);
-                    self.jmpif_label(then_label);
-                    self.jmp_label(else_label);
-                    self.set_jmp_label(then_label);
+                    self.program.push(Inst::JmpIf(then_label));
+                    self.program.push(Inst::Jmp(else_label));
+                    self.program.push(Inst::Label(then_label));
 
                     self.compile_block(&elzeif.then, lup);
 
-                    self.jmp_label(end_label);
+                    self.program.push(Inst::Jmp(end_label));
                 }
 
-                self.set_jmp_label(else_label);
+                self.program.push(Inst::Label(else_label));
 
                 self.compile_block(elze, lup);
 
-                self.set_jmp_label(end_label);
+                self.program.push(Inst::Label(end_label));
             },
 
             StmtKind::For { body, init, cond, post } => {
-                let forlup = Loop {
-                    start: self.new_jmp_label(),
-                    end:   self.new_jmp_label(),
-                };
-
                 let forloop_scope = self.curr_scope_idx;
 
                 if let Some(s) = init {
-                    self.compile_stmt(s, forloop_scope, &lup);
+                    self.compile_stmt(s, forloop_scope, lup);
                 }
 
-                self.set_jmp_label(forlup.start);
-                if let Some(e) = cond {
-                    let forloop_body = self.new_jmp_label();
-                    self.compile_expr(&e.kind, forloop_scope);
-                    self.jmpif_label(forloop_body);
-                    self.jmp_label(forlup.end);
-                    self.set_jmp_label(forloop_body);
-                }
-
-                self.compile_block(body, &forlup);
+                self.compile_loop(cond.as_ref(), body, post.as_deref(), forloop_scope);
+            },
 
-                if let Some(s) = post {
-                    self.compile_stmt(s, forloop_scope, &forlup);
-                }
+            StmtKind::While { cond, body } => {
+                self.compile_loop(Some(cond), body, None, scope);
+            },
 
-                self.jmp_label(forlup.start);
-                self.set_jmp_label(forlup.end);
+            StmtKind::Loop(body) => {
+                self.compile_loop(None, body, None, scope);
             },
 
             StmtKind::Break => {
-                self.jmp_label(lup.end);
+                self.program.push(Inst::Jmp(lup.end));
             },
 
             StmtKind::Continue => {
-                self.jmp_label(lup.start);
+                self.program.push(Inst::Jmp(lup.start));
+            },
+
+            // the vm continues with the next instruction on the next tick
+            StmtKind::Yield => {
+                self.program.push(Inst::Const(1));
+                self.program.push(Inst::Sleep);
+            },
+
+            StmtKind::Sleep(ticks) => {
+                self.compile_expr(&ticks.kind, scope);
+                self.program.push(Inst::Sleep);
+            },
+
+            StmtKind::Print(expr) => {
+                self.compile_text(&expr.kind, scope);
+                self.program.push(Inst::Print);
+            },
+
+            StmtKind::Log { level, format, args } => {
+                if self.log_level.is_none_or(|min| *level < min) { return; }
+                self.compile_format(format, args, scope);
+                self.program.push(Inst::Log(*level));
             },
 
-            StmtKind::ExternFnDecl { name, param_count } => {
+            // struct parameters are passed field by field
+            StmtKind::ExternFnDecl { name, params, result } => {
+                let (_, param_count) = self.frame_sizes(name);
+                let has_result = result.is_some() as usize;
                 self.set_call_label(name);
 
                 // creating new stack
-                inst!(self, "get_reg {{_:sp2}}");
-                cmd!(self, "scoreboard players operation sp2 redvm.regs = sp redvm.regs");
-                cmd!(self, "scoreboard players remove sp2 redvm.regs {}", param_count+2);
-
-                // loading args to extern function
-                for i in 0..*param_count {
-                    cmd!(self, "execute store result storage redvm eargs.{i} int 1 run function redvm:insts/see_local {{_:{i}}}");
+                self.program.push(Inst::GetReg(Reg::Sp2));
+                self.program.push(Inst::RegCopy(Reg::Sp2, Reg::Sp));
+                self.program.push(Inst::RegSub(Reg::Sp2, param_count+has_result+2));
+
+                // loading args to extern function, strings are passed as their text
+                let params: Vec<DataType> = params.iter().flat_map(|ty| slots(&self.scopes, *ty)).collect();
+                for (i, slot) in params.into_iter().enumerate() {
+                    self.program.push(match slot {
+                        DataType::Str => Inst::LoadStrArg(i+has_result, i),
+                        _ => Inst::LoadExternArg(i+has_result, i),
+                    });
                 }
 
-                // calling extern function 
-                cmd!(self, "function redvm:extern/{name} with storage redvm eargs");
+                // calling extern function, the returned value goes to the result slot
+                self.program.push(Inst::CallExtern(name, result.is_some()));
+                if result.is_some() {
+                    self.program.push(Inst::PushExternResult);
+                    self.program.push(Inst::SetLocal(0));
+                }
 
                 // return
-                inst!(self, "set_reg {{_:sp2}}");
-                inst!(self, "set_reg {{_:ip}}");
+                self.program.push(Inst::SetReg(Reg::Sp2));
+                self.program.push(Inst::SetReg(Reg::Ip));
             },
 
             StmtKind::FnDecl(data) => {
                 self.set_call_label(data.name);
+                self.ret_label = self.program.new_label();
 
-                let ret_label = self.new_jmp_label();
-                assert_eq!(ret_label, 0);
-
-                let local_len = if let Type::FnDecl(info) = self.scopes[0].items.get(data.name).unwrap() {
-                    info.local_count
-                } else {
-                    unreachable!()
-                };
+                let local_len = self.fn_info(data.name).local_count;
+                let (result_size, params_size) = self.frame_sizes(data.name);
+                self.ret_type = data.result;
 
                 // creating stack frame
-                inst!(self, "get_reg {{_:sp2}}");
-                cmd!(self, "scoreboard players operation sp2 redvm.regs = sp redvm.regs");
-                cmd!(self, "scoreboard players remove sp2 redvm.regs {}", data.params.len()+data.has_result as usize + 2);
-                cmd!(self, "scoreboard players add sp redvm.regs {}", local_len);
+                self.program.push(Inst::GetReg(Reg::Sp2));
+                self.program.push(Inst::RegCopy(Reg::Sp2, Reg::Sp));
+                self.program.push(Inst::RegSub(Reg::Sp2, params_size + result_size + 2));
+                self.program.push(Inst::RegAdd(Reg::Sp, local_len));
 
                 self.compile_block(&data.body, &Loop { start: 0, end: 0 });
 
-                self.set_jmp_label(ret_label);
-                cmd!(self, "scoreboard players remove sp redvm.regs {}", local_len);
-                inst!(self, "set_reg {{_:sp2}}");
-                inst!(self, "set_reg {{_:ip}}");
-
-                self.write_jmp_labels();
+                self.program.push(Inst::Label(self.ret_label));
+                self.program.push(Inst::RegSub(Reg::Sp, local_len));
+                self.program.push(Inst::SetReg(Reg::Sp2));
+                self.program.push(Inst::SetReg(Reg::Ip));
             },
         }
     }
 
-    fn compile_block(&mut self, block: &Block<'a>, lup: &Loop) {
-        let curr_scope = self.curr_scope_idx;
-        self.curr_scope_idx += 1;
-        for stmt in block { self.compile_stmt(stmt, curr_scope, lup); }
-    }
+    // `continue` jumps to `lup.start`, which is placed before `post`
+    fn compile_loop(&mut self, cond: Option<&'a Expr>, body: &'a Block<'a>, post: Option<&'a Stmt<'a>>, scope: ScopeIdx) {
+        let top = self.program.new_label();
+        let lup = Loop {
+            start: self.program.new_label(),
+            end:   self.program.new_label(),
+        };
+
+        self.program.push(Inst::Label(top));
+        if let Some(e) = cond {
+            let body_label = self.program.new_label();
+            self.compile_expr(&e.kind, scope);
+            self.program.push(Inst::JmpIf(body_label));
+            self.program.push(Inst::Jmp(lup.end));
+            self.program.push(Inst::Label(body_label));
+        }
 
-    fn curr_pos(&mut self) -> FilePos {
-        self.file.stream_position().unwrap_or_else(|err| {
-            compilation_err!("Could not get current file position: {err}");
-        })
-    }
+        self.compile_block(body, &lup);
 
-    fn seek(&mut self, pos: FilePos) {
-        self.file.seek(SeekFrom::Start(pos)).unwrap_or_else(|err| {
-            compilation_err!("Could not seek file: {err}");
-        });
+        self.program.push(Inst::Label(lup.start));
+        if let Some(s) = post {
+            self.compile_stmt(s, scope, &lup);
+        }
+
+        self.program.push(Inst::Jmp(top));
+        self.program.push(Inst::Label(lup.end));
     }
 
-    fn binop_to_inst(binop: BinOpKind) -> &'static str {
-        match binop {
-            BinOpKind::Add => "add",
-            BinOpKind::Sub => "sub",
-            BinOpKind::Mul => "mul",
-            BinOpKind::Div => "div",
-            BinOpKind::Mod => "mod",
-            BinOpKind::Gt  => "gt",
-            BinOpKind::Ge  => "ge",
-            BinOpKind::Lt  => "lt",
-            BinOpKind::Le  => "le",
-            BinOpKind::Eq  => "eq",
-            BinOpKind::Ne  => "ne",
-            BinOpKind::And => "and",
-            BinOpKind::Or  => "or",
-        }
+    fn compile_block(&mut self, block: &'a Block<'a>, lup: &Loop) {
+        let curr_scope = self.curr_scope_idx;
+        self.curr_scope_idx += 1;
+        for stmt in block { self.compile_stmt(stmt, curr_scope, lup); }
     }
 }
 
-pub fn compile(file: File, ast: &Ast, semdata: Vec<Scope>) {
-    //println!("{semdata:#?}");
+pub fn compile<'a>(ast: &'a Ast<'a>, semdata: Vec<Scope<'a>>, log_level: Option<LogLevel>) -> Program<'a> {
     let mut comp = Compiler {
         call_labels: HashMap::new(),
-        call_label_usages: Vec::new(),
-        jmp_labels: Vec::new(),
-        jmp_label_usages: Vec::new(),
+        ret_label: 0,
+        ret_type: None,
+        log_level,
         curr_scope_idx: 0,
         scopes: semdata,
-        file,
-        ip: 0
+        program: Program::default(),
     };
 
-    comp.call_label("main");
-    cmd!(comp, "scoreboard players set ip redvm.regs 1000");
-    comp.compile_block(&ast.stmts, &Loop { start: 0, end: 0 });
+    let is_global = |stmt: &Stmt| matches!(stmt.kind, StmtKind::VarDecl(..) | StmtKind::VarDeclAssign { .. });
+    let lup = Loop { start: 0, end: 0 };
+    comp.curr_scope_idx = 1;
 
-    comp.write_call_labels();
+    let entries = |entry: Entry| ast.stmts.iter().filter_map(move |s| match &s.kind {
+        StmtKind::FnDecl(data) if data.entry == Some(entry) => Some(data.name),
+        _ => None,
+    });
 
-    //println!("{ast:#?}");
+    // globals are initialized in order before the `#[load]` functions and
+    // `main` are called, the program stops when they return and the vm runs
+    // off the end
+    for stmt in ast.stmts.iter().filter(|s| is_global(s)) {
+        comp.compile_stmt(stmt, 0, &lup);
+    }
+    let end_label = comp.program.new_label();
+    for name in entries(Entry::Load) {
+        let label = comp.call_label(name);
+        comp.program.push(Inst::Call(label));
+    }
+    if matches!(comp.scopes[0].items.get("main"), Some(Type::FnDecl(_))) {
+        let main_label = comp.call_label("main");
+        comp.program.push(Inst::Call(main_label));
+    }
+    comp.program.push(Inst::Jmp(end_label));
+
+    // `redvm:invoke` runs it while the vm is idle
+    if entries(Entry::Tick).next().is_some() {
+        let tick_label = comp.program.new_label();
+        comp.program.push(Inst::Label(tick_label));
+        for name in entries(Entry::Tick) {
+            let label = comp.call_label(name);
+            comp.program.push(Inst::Call(label));
+        }
+        comp.program.push(Inst::Jmp(end_label));
+        comp.program.tick = Some(tick_label);
+    }
+    comp.program.autostart = entries(Entry::Load).chain(entries(Entry::Tick)).next().is_some();
+
+    for stmt in ast.stmts.iter().filter(|s| !is_global(s)) {
+        comp.compile_stmt(stmt, 0, &lup);
+    }
+
+    comp.program.push(Inst::Label(end_label));
+    comp.program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{emitter, lexer::{BinOpKind, Lexer}, parser, semantic};
+
+    #[test]
+    fn function_frame() {
+        let mut lexer = Lexer::new(b"fn add(a, b) int { return a + b } fn main() {}", 0);
+        let ast = parser::parse(&mut lexer).unwrap();
+        let (scopes, _) = semantic::Analyzer::analyze(&ast);
+        let program = compile(&ast, scopes, Some(LogLevel::Debug));
+
+        assert_eq!(program.insts, [
+            Inst::Call(1),
+            Inst::Jmp(0),
+            Inst::Fn("add"),
+            Inst::Label(2),
+            Inst::GetReg(Reg::Sp2),
+            Inst::RegCopy(Reg::Sp2, Reg::Sp),
+            Inst::RegSub(Reg::Sp2, 5),
+            Inst::RegAdd(Reg::Sp, 0),
+            Inst::GetLocal(1),
+            Inst::GetLocal(2),
+            Inst::BinOp(BinOpKind::Add),
+            Inst::SetLocal(0),
+            Inst::Jmp(3),
+            Inst::Label(3),
+            Inst::RegSub(Reg::Sp, 0),
+            Inst::SetReg(Reg::Sp2),
+            Inst::SetReg(Reg::Ip),
+            Inst::Fn("main"),
+            Inst::Label(1),
+            Inst::GetReg(Reg::Sp2),
+            Inst::RegCopy(Reg::Sp2, Reg::Sp),
+            Inst::RegSub(Reg::Sp2, 2),
+            Inst::RegAdd(Reg::Sp, 0),
+            Inst::Label(4),
+            Inst::RegSub(Reg::Sp, 0),
+            Inst::SetReg(Reg::Sp2),
+            Inst::SetReg(Reg::Ip),
+            Inst::Label(0),
+        ]);
+    }
+
+    #[test]
+    fn entry_stubs() {
+        let mut lexer = Lexer::new(b"#[tick] fn b() {} #[load] fn a() {}", 0);
+        let ast = parser::parse(&mut lexer).unwrap();
+        let (scopes, _) = semantic::Analyzer::analyze(&ast);
+        let program = compile(&ast, scopes, Some(LogLevel::Debug));
+
+        assert_eq!(program.insts[..6], [
+            Inst::Call(1),
+            Inst::Jmp(0),
+            Inst::Label(2),
+            Inst::Call(3),
+            Inst::Jmp(0),
+            Inst::Fn("b"),
+        ]);
+        assert_eq!((program.tick, program.autostart), (Some(2), true));
+        assert_eq!(emitter::tick_ip(&program), Some(2));
+    }
 }
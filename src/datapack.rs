@@ -0,0 +1,347 @@
+use std::path::Path;
+
+use crate::{compilation_err, exit_failure, parser::LogLevel, vm};
+
+// Datapack assembly. Backends produce a set of files (path inside the pack ->
+// content) and `write` puts them either into a directory or into a `.zip`.
+
+pub type Files = std::collections::BTreeMap<String, String>;
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub namespace:   String,
+    pub pack_format: u32,
+    pub max_stack:   i32,
+    pub insts_per_tick: i32,
+    // `None` leaves out every log statement
+    pub log_level:  Option<LogLevel>,
+    pub log_target: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            namespace: "luba".to_string(), pack_format: 48, max_stack: vm::DEFAULT_MAX_STACK, insts_per_tick: vm::DEFAULT_INSTS_PER_TICK,
+            log_level: Some(LogLevel::Debug), log_target: "@a".to_string(),
+        }
+    }
+}
+
+macro_rules! runtime {
+    ($($path:literal,)*) => {
+        &[$(($path, include_str!(concat!("../redvm/", $path))),)*]
+    };
+}
+
+// the redvm runtime is embedded into the compiler, so generated datapacks
+// don't need the `redvm` pack next to them
+pub const RUNTIME: &[(&str, &str)] = runtime![
+    "data/minecraft/tags/function/tick.json",
+    "data/redvm/function/insts/add.mcfunction",
+    "data/redvm/function/insts/and.mcfunction",
+    "data/redvm/function/insts/arr_global.mcfunction",
+    "data/redvm/function/insts/arr_local.mcfunction",
+    "data/redvm/function/insts/bnot.mcfunction",
+    "data/redvm/function/insts/call.mcfunction",
+    "data/redvm/function/insts/const.mcfunction",
+    "data/redvm/function/insts/div.mcfunction",
+    "data/redvm/function/insts/eq.mcfunction",
+    "data/redvm/function/insts/ge.mcfunction",
+    "data/redvm/function/insts/get_elem_global.mcfunction",
+    "data/redvm/function/insts/get_elem_local.mcfunction",
+    "data/redvm/function/insts/get_global.mcfunction",
+    "data/redvm/function/insts/get_local.mcfunction",
+    "data/redvm/function/insts/get_reg.mcfunction",
+    "data/redvm/function/insts/get_str_global.mcfunction",
+    "data/redvm/function/insts/get_str_local.mcfunction",
+    "data/redvm/function/insts/gt.mcfunction",
+    "data/redvm/function/insts/jmp_if.mcfunction",
+    "data/redvm/function/insts/le.mcfunction",
+    "data/redvm/function/insts/load_str_global.mcfunction",
+    "data/redvm/function/insts/load_str_local.mcfunction",
+    "data/redvm/function/insts/log.mcfunction",
+    "data/redvm/function/insts/lt.mcfunction",
+    "data/redvm/function/insts/mod.mcfunction",
+    "data/redvm/function/insts/mul.mcfunction",
+    "data/redvm/function/insts/ne.mcfunction",
+    "data/redvm/function/insts/neg.mcfunction",
+    "data/redvm/function/insts/not.mcfunction",
+    "data/redvm/function/insts/op_global.mcfunction",
+    "data/redvm/function/insts/op_local.mcfunction",
+    "data/redvm/function/insts/or.mcfunction",
+    "data/redvm/function/insts/print.mcfunction",
+    "data/redvm/function/insts/see_global.mcfunction",
+    "data/redvm/function/insts/see_local.mcfunction",
+    "data/redvm/function/insts/set_elem_global.mcfunction",
+    "data/redvm/function/insts/set_elem_local.mcfunction",
+    "data/redvm/function/insts/set_global.mcfunction",
+    "data/redvm/function/insts/set_local.mcfunction",
+    "data/redvm/function/insts/set_reg.mcfunction",
+    "data/redvm/function/insts/set_str_global.mcfunction",
+    "data/redvm/function/insts/set_str_local.mcfunction",
+    "data/redvm/function/insts/sleep.mcfunction",
+    "data/redvm/function/insts/str_cat.mcfunction",
+    "data/redvm/function/insts/str_const.mcfunction",
+    "data/redvm/function/insts/sub.mcfunction",
+    "data/redvm/function/insts/to_str.mcfunction",
+    "data/redvm/function/invoke.mcfunction",
+    "data/redvm/function/loop.mcfunction",
+    "data/redvm/function/run.mcfunction",
+    "data/redvm/function/tick.mcfunction",
+    "data/redvm/function/utils/add.mcfunction",
+    "data/redvm/function/utils/and.mcfunction",
+    "data/redvm/function/utils/arr_local.mcfunction",
+    "data/redvm/function/utils/bnot.mcfunction",
+    "data/redvm/function/utils/bounds_error.mcfunction",
+    "data/redvm/function/utils/const.mcfunction",
+    "data/redvm/function/utils/div.mcfunction",
+    "data/redvm/function/utils/eq.mcfunction",
+    "data/redvm/function/utils/error.mcfunction",
+    "data/redvm/function/utils/ge.mcfunction",
+    "data/redvm/function/utils/get_elem_global.mcfunction",
+    "data/redvm/function/utils/get_elem_local.mcfunction",
+    "data/redvm/function/utils/get_global.mcfunction",
+    "data/redvm/function/utils/get_inst.mcfunction",
+    "data/redvm/function/utils/get_reg.mcfunction",
+    "data/redvm/function/utils/get_str_global.mcfunction",
+    "data/redvm/function/utils/gt.mcfunction",
+    "data/redvm/function/utils/index.mcfunction",
+    "data/redvm/function/utils/jmp_if.mcfunction",
+    "data/redvm/function/utils/le.mcfunction",
+    "data/redvm/function/utils/load_str_local.mcfunction",
+    "data/redvm/function/utils/log.mcfunction",
+    "data/redvm/function/utils/lt.mcfunction",
+    "data/redvm/function/utils/mod.mcfunction",
+    "data/redvm/function/utils/mul.mcfunction",
+    "data/redvm/function/utils/ne.mcfunction",
+    "data/redvm/function/utils/neg.mcfunction",
+    "data/redvm/function/utils/not.mcfunction",
+    "data/redvm/function/utils/op_global.mcfunction",
+    "data/redvm/function/utils/op_local.mcfunction",
+    "data/redvm/function/utils/or.mcfunction",
+    "data/redvm/function/utils/print.mcfunction",
+    "data/redvm/function/utils/run_cmd.mcfunction",
+    "data/redvm/function/utils/see_local.mcfunction",
+    "data/redvm/function/utils/set_elem_global.mcfunction",
+    "data/redvm/function/utils/set_elem_local.mcfunction",
+    "data/redvm/function/utils/set_global.mcfunction",
+    "data/redvm/function/utils/set_local.mcfunction",
+    "data/redvm/function/utils/set_reg.mcfunction",
+    "data/redvm/function/utils/set_str_global.mcfunction",
+    "data/redvm/function/utils/set_str_local.mcfunction",
+    "data/redvm/function/utils/sleep.mcfunction",
+    "data/redvm/function/utils/str_cat.mcfunction",
+    "data/redvm/function/utils/str_cat_set.mcfunction",
+    "data/redvm/function/utils/str_const.mcfunction",
+    "data/redvm/function/utils/sub.mcfunction",
+    "data/redvm/function/utils/to_str.mcfunction",
+    "data/redvm/function/utils/to_str_set.mcfunction",
+];
+
+pub fn mcmeta(opts: &Options, description: &str) -> String {
+    format!(
+        "{{\n    \"pack\": {{\n        \"pack_format\": {},\n        \"description\": \"{description}\"\n    }}\n}}\n",
+        opts.pack_format
+    )
+}
+
+pub fn function_tag(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|v| format!("        \"{v}\"")).collect();
+    format!("{{\n    \"values\": [\n{}\n    ]\n}}\n", values.join(",\n"))
+}
+
+// `#minecraft:load` and `#minecraft:tick`, empty tags are left out
+pub fn entry_tags(files: &mut Files, load: &[String], tick: &[String]) {
+    for (tag, values) in [("load", load), ("tick", tick)] {
+        if !values.is_empty() {
+            files.insert(format!("data/minecraft/tags/function/{tag}.json"), function_tag(values));
+        }
+    }
+}
+
+// a datapack that runs `program` (the output of `emitter::emit`) on redvm:
+// `#minecraft:load` prepares the scoreboards, `/function <ns>:run` starts
+// the program and `#minecraft:tick` drives the vm. `<ns>:tick` invokes
+// the program at `tick_ip` before the vm runs, `autostart` programs are
+// started by `<ns>:load`
+pub fn redvm(program: String, macros: Vec<(String, String)>, tick_ip: Option<usize>, autostart: bool, opts: &Options) -> Files {
+    let ns = &opts.namespace;
+    let mut files = Files::new();
+    for (path, content) in RUNTIME {
+        files.insert(path.to_string(), content.to_string());
+    }
+
+    files.insert("pack.mcmeta".to_string(), mcmeta(opts, "luba program running on redvm"));
+    files.insert(format!("data/{ns}/function/program.mcfunction"), program);
+    for (id, body) in macros {
+        let (ns, path) = id.split_once(':').unwrap();
+        files.insert(format!("data/{ns}/function/{path}.mcfunction"), body);
+    }
+    let mut load = "\
+scoreboard objectives add redvm.local dummy
+scoreboard objectives add redvm.global dummy
+scoreboard objectives add redvm.regs dummy
+scoreboard players set running redvm.regs 0
+".to_string();
+    if autostart {
+        load += &format!("function {ns}:run\n");
+    }
+    files.insert(format!("data/{ns}/function/load.mcfunction"), load);
+    files.insert(
+        format!("data/{ns}/function/run.mcfunction"),
+        format!(
+            "scoreboard players set max_sp redvm.regs {}\nscoreboard players set ipt redvm.regs {}\ndata modify storage redvm log_target set value \"{}\"\nfunction redvm:run {{load_program:\"function {ns}:program\"}}\n",
+            opts.max_stack, opts.insts_per_tick, opts.log_target.replace('"', "\\\"")
+        )
+    );
+
+    let mut tick = vec!["redvm:tick".to_string()];
+    if let Some(ip) = tick_ip {
+        files.insert(format!("data/{ns}/function/tick.mcfunction"), format!("function redvm:invoke {{ip:{ip}}}\n"));
+        tick.insert(0, format!("{ns}:tick"));
+    }
+    entry_tags(&mut files, &[format!("{ns}:load")], &tick);
+
+    files
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// stores the files without compression, datapacks are small enough
+pub fn zip(files: &Files) -> Vec<u8> {
+    const VERSION: u16 = 20;
+    const DOS_DATE: u16 = 0x21; // 1980-01-01
+
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for (path, content) in files {
+        let offset = out.len() as u32;
+        let (crc, size, name_len) = (crc32(content.as_bytes()), content.len() as u32, path.len() as u16);
+
+        out.extend(0x0403_4b50u32.to_le_bytes());
+        for field in [VERSION, 0, 0, 0, DOS_DATE] { out.extend(field.to_le_bytes()); }
+        for field in [crc, size, size] { out.extend(field.to_le_bytes()); }
+        for field in [name_len, 0] { out.extend(field.to_le_bytes()); }
+        out.extend(path.as_bytes());
+        out.extend(content.as_bytes());
+
+        central.extend(0x0201_4b50u32.to_le_bytes());
+        for field in [VERSION, VERSION, 0, 0, 0, DOS_DATE] { central.extend(field.to_le_bytes()); }
+        for field in [crc, size, size] { central.extend(field.to_le_bytes()); }
+        for field in [name_len, 0, 0, 0, 0] { central.extend(field.to_le_bytes()); }
+        for field in [0, offset] { central.extend(field.to_le_bytes()); }
+        central.extend(path.as_bytes());
+    }
+
+    let (central_offset, central_size) = (out.len() as u32, central.len() as u32);
+    out.extend(central);
+    out.extend(0x0605_4b50u32.to_le_bytes());
+    for field in [0, 0, files.len() as u16, files.len() as u16] { out.extend(field.to_le_bytes()); }
+    for field in [central_size, central_offset] { out.extend(field.to_le_bytes()); }
+    out.extend(0u16.to_le_bytes());
+    out
+}
+
+// `<path>.zip` is written as an archive, anything else as a directory
+pub fn write(path: &Path, files: &Files) {
+    if path.extension().is_some_and(|ext| ext == "zip") {
+        std::fs::write(path, zip(files)).unwrap_or_else(|err| {
+            compilation_err!("Could not write `{}`: {err}", path.display());
+        });
+        return;
+    }
+
+    for (file, content) in files {
+        let file = path.join(file);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent).unwrap_or_else(|err| {
+                compilation_err!("Could not create directory `{}`: {err}", parent.display());
+            });
+        }
+
+        std::fs::write(&file, content).unwrap_or_else(|err| {
+            compilation_err!("Could not write `{}`: {err}", file.display());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_is_complete() {
+        fn collect(dir: &Path, root: &Path, paths: &mut Vec<String>) {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    collect(&path, root, paths);
+                } else {
+                    paths.push(path.strip_prefix(root).unwrap().to_string_lossy().into_owned());
+                }
+            }
+        }
+
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("redvm");
+        let mut paths = Vec::new();
+        collect(&root.join("data"), &root, &mut paths);
+        paths.sort();
+
+        let runtime: Vec<&str> = RUNTIME.iter().map(|(path, _)| *path).collect();
+        assert_eq!(paths, runtime);
+    }
+
+    #[test]
+    fn redvm_pack() {
+        let program = "data modify storage redvm insts append value 'function luba:cmd/0 with storage redvm eargs'\n";
+        let macros = vec![("luba:cmd/0".to_string(), "$say $(0)\n".to_string())];
+        let files = redvm(program.to_string(), macros, None, false, &Options::default());
+        assert_eq!(files["data/luba/function/cmd/0.mcfunction"], "$say $(0)\n");
+        assert_eq!(files["data/luba/function/run.mcfunction"], "\
+scoreboard players set max_sp redvm.regs 1024
+scoreboard players set ipt redvm.regs 256
+data modify storage redvm log_target set value \"@a\"
+function redvm:run {load_program:\"function luba:program\"}
+");
+        assert_eq!(files["data/minecraft/tags/function/load.json"], "{\n    \"values\": [\n        \"luba:load\"\n    ]\n}\n");
+        assert_eq!(files["data/minecraft/tags/function/tick.json"], "{\n    \"values\": [\n        \"redvm:tick\"\n    ]\n}\n");
+        assert!(files.contains_key("data/redvm/function/insts/call.mcfunction"));
+        assert!(files["pack.mcmeta"].contains("\"pack_format\": 48"));
+        assert!(!files.contains_key("data/luba/function/tick.mcfunction"));
+    }
+
+    #[test]
+    fn redvm_entries() {
+        let files = redvm(String::new(), Vec::new(), Some(12), true, &Options::default());
+        assert!(files["data/luba/function/load.mcfunction"].ends_with("function luba:run\n"));
+        assert_eq!(files["data/luba/function/tick.mcfunction"], "function redvm:invoke {ip:12}\n");
+        assert_eq!(
+            files["data/minecraft/tags/function/tick.json"],
+            "{\n    \"values\": [\n        \"luba:tick\",\n        \"redvm:tick\"\n    ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn zip_archive() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let mut files = Files::new();
+        files.insert("pack.mcmeta".to_string(), "{}".to_string());
+        let zip = zip(&files);
+
+        assert_eq!(zip[..4], [0x50, 0x4b, 0x03, 0x04]);
+        assert_eq!(zip[30..41], *b"pack.mcmeta");
+        assert_eq!(zip[41..43], *b"{}");
+        assert_eq!(zip[zip.len()-22..zip.len()-18], [0x50, 0x4b, 0x05, 0x06]);
+        assert_eq!(zip.len(), 30 + 11 + 2 + 46 + 11 + 22);
+    }
+}
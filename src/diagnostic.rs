@@ -0,0 +1,136 @@
+use std::fmt::{self, Write};
+
+use crate::lexer::Span;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Note {
+    pub span:    Option<Span>,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span:     Option<Span>,
+    pub kind:     &'static str,
+    pub message:  String,
+    pub notes:    Vec<Note>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Option<Span>, kind: &'static str, message: String) -> Self {
+        Self { severity: Severity::Error, span, kind, message, notes: Vec::new() }
+    }
+
+    pub fn warning(span: Option<Span>, kind: &'static str, message: String) -> Self {
+        Self { severity: Severity::Warning, span, kind, message, notes: Vec::new() }
+    }
+
+    pub fn with_note(mut self, span: Option<Span>, message: &str) -> Self {
+        self.notes.push(Note { span, message: message.to_string() });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // renders the diagnostic with the source lines it points to:
+    //
+    // ERROR: SemanticError: Variable `y` is not found
+    //  --> example.luba:3:10
+    //   |
+    // 3 |     x := y + 1
+    //   |          ^
+    pub fn render(&self, file_path: &str, src: &str) -> String {
+        let rows = self.notes.iter()
+            .filter_map(|note| note.span.as_ref())
+            .chain(self.span.as_ref())
+            .map(|span| span.start.row);
+        let gutter = rows.max().unwrap_or(0).to_string().len();
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{}: {}: {}", self.severity, self.kind, self.message);
+        render_span(&mut out, file_path, src, self.span.as_ref(), gutter);
+        for note in &self.notes {
+            let _ = writeln!(out, "note: {}", note.message);
+            render_span(&mut out, file_path, src, note.span.as_ref(), gutter);
+        }
+        out
+    }
+}
+
+fn render_span(out: &mut String, file_path: &str, src: &str, span: Option<&Span>, gutter: usize) {
+    let Some(span) = span else {
+        let _ = writeln!(out, "{:gutter$}--> {file_path}", "");
+        return;
+    };
+
+    let _ = writeln!(out, "{:gutter$}--> {file_path}:{}", "", span.start);
+
+    let Some(line) = src.lines().nth(span.start.row-1) else { return; };
+    let line = line.replace('\t', " ");
+    let len = if span.end.row == span.start.row {
+        span.end.col.saturating_sub(span.start.col)
+    } else {
+        line.len().saturating_sub(span.start.col-1)
+    }.max(1);
+
+    let _ = writeln!(out, "{:gutter$} |", "");
+    let _ = writeln!(out, "{:>gutter$} | {line}", span.start.row);
+    let _ = writeln!(out, "{:gutter$} | {:pad$}{}", "", "", "^".repeat(len), pad = span.start.col-1);
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error   => write!(f, "ERROR"),
+            Severity::Warning => write!(f, "WARNING"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{}:{span}: {}: {}", self.severity, self.kind, self.message),
+            None       => write!(f, "{}: {}: {}", self.severity, self.kind, self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Loc;
+
+    fn span(row: usize, start: usize, end: usize) -> Option<Span> {
+        Some(Span { file: 0, start: Loc { row, col: start }, end: Loc { row, col: end } })
+    }
+
+    #[test]
+    fn render() {
+        let src = "fn add(a, b) int {\n    return a + b\n}\n\nfn main() {\n    x := add(1)\n}\n";
+        let diagnostic = Diagnostic::error(span(6, 10, 16), "SemanticError", "Wrong arguments".to_string())
+            .with_note(span(1, 1, 19), "function declared here");
+
+        assert_eq!(diagnostic.render("example.luba", src), "\
+ERROR: SemanticError: Wrong arguments
+ --> example.luba:6:10
+  |
+6 |     x := add(1)
+  |          ^^^^^^
+note: function declared here
+ --> example.luba:1:1
+  |
+1 | fn add(a, b) int {
+  | ^^^^^^^^^^^^^^^^^^
+");
+    }
+}
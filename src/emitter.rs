@@ -0,0 +1,161 @@
+use std::io::Write;
+
+use crate::{compilation_err, exit_failure, ir::{Inst, Program}, lexer::{BinOpKind, UnOpKind}, parser::LogLevel};
+
+type IP = usize;
+
+macro_rules! write_ln {
+    ($file:expr, $($args:tt)*) => {
+        let _ = write!($file, $($args)*).unwrap_or_else(|err| {
+            compilation_err!("Could not write: {err}");
+        });
+    }
+}
+
+// every instruction is a single-quoted SNBT string
+macro_rules! cmd {
+    ($file:expr, $($arg:tt)*) => {
+        write_ln!($file, "data modify storage redvm insts append value '{}'\n", escape(&format!($($arg)*)));
+    };
+}
+
+macro_rules! inst {
+    ($file:expr, $($arg:tt)*) => {
+        cmd!($file, "function redvm:insts/{}", format!($($arg)*));
+    };
+}
+
+fn escape(cmd: &str) -> String {
+    cmd.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn resolve_labels(program: &Program) -> Vec<IP> {
+    let mut labels: Vec<Option<IP>> = vec![None; program.label_count];
+    let mut ip: IP = 0;
+    for inst in &program.insts {
+        match inst {
+            Inst::Label(label) => labels[*label] = Some(ip),
+            Inst::Fn(_) => {},
+            _ => ip += 1,
+        }
+    }
+
+    labels.into_iter().enumerate().map(|(label, ip)| {
+        ip.unwrap_or_else(|| {
+            compilation_err!("Label `L{label}` is not defined");
+        })
+    }).collect()
+}
+
+// the ip of the `#[tick]` stub, `<ns>:tick` passes it to `redvm:invoke`
+pub fn tick_ip(program: &Program) -> Option<IP> {
+    program.tick.map(|label| resolve_labels(program)[label])
+}
+
+// macro functions of the program as `(<ns>:cmd/<idx>, body)`
+pub fn macros(program: &Program, ns: &str) -> Vec<(String, String)> {
+    program.macros.iter().enumerate()
+        .map(|(idx, body)| (format!("{ns}:cmd/{idx}"), format!("{body}\n")))
+        .collect()
+}
+
+pub fn emit<W: Write>(mut file: W, program: &Program, ns: &str) {
+    let labels = resolve_labels(program);
+    for inst in &program.insts {
+        match inst {
+            Inst::Fn(name)           => { write_ln!(file, "\n# {name}\n"); },
+            Inst::Label(_)           => {},
+            Inst::Const(n)           => { inst!(file, "const {{_:{n}}}"); },
+            Inst::GetLocal(idx)      => { inst!(file, "get_local {{_:{idx}}}"); },
+            Inst::SetLocal(idx)      => { inst!(file, "set_local {{_:{idx}}}"); },
+            Inst::GetGlobal(idx)     => { inst!(file, "get_global {{_:{idx}}}"); },
+            Inst::SetGlobal(idx)     => { inst!(file, "set_global {{_:{idx}}}"); },
+            Inst::OpLocal(idx, op)   => { inst!(file, "op_local {{_:{idx},op:\"{op}=\"}}"); },
+            Inst::OpGlobal(idx, op)  => { inst!(file, "op_global {{_:{idx},op:\"{op}=\"}}"); },
+            Inst::ArrLocal(idx, value)  => { inst!(file, "arr_local {{_:{idx},value:{}}}", nbt_list(value)); },
+            Inst::ArrGlobal(idx, value) => { inst!(file, "arr_global {{_:{idx},value:{}}}", nbt_list(value)); },
+            Inst::GetElemLocal(idx, len)  => { inst!(file, "get_elem_local {{_:{idx},len:{len}}}"); },
+            Inst::GetElemGlobal(idx, len) => { inst!(file, "get_elem_global {{_:{idx},len:{len}}}"); },
+            Inst::SetElemLocal(idx, len)  => { inst!(file, "set_elem_local {{_:{idx},len:{len}}}"); },
+            Inst::SetElemGlobal(idx, len) => { inst!(file, "set_elem_global {{_:{idx},len:{len}}}"); },
+            Inst::StrConst(text)     => { inst!(file, "str_const {{_:\"{text}\"}}"); },
+            Inst::GetStrLocal(idx)   => { inst!(file, "get_str_local {{_:{idx}}}"); },
+            Inst::SetStrLocal(idx)   => { inst!(file, "set_str_local {{_:{idx}}}"); },
+            Inst::GetStrGlobal(idx)  => { inst!(file, "get_str_global {{_:{idx}}}"); },
+            Inst::SetStrGlobal(idx)  => { inst!(file, "set_str_global {{_:{idx}}}"); },
+            Inst::ToStr              => { inst!(file, "to_str"); },
+            Inst::StrCat             => { inst!(file, "str_cat"); },
+            Inst::Print              => { inst!(file, "print"); },
+            Inst::Log(level)         => { inst!(file, "log {{_:\"{level}\",color:\"{}\"}}", log_color(level)); },
+            Inst::GetReg(reg)        => { inst!(file, "get_reg {{_:{reg}}}"); },
+            Inst::SetReg(reg)        => { inst!(file, "set_reg {{_:{reg}}}"); },
+            Inst::BinOp(kind)        => { inst!(file, "{}", binop_to_inst(kind)); },
+            Inst::UnaryOp(kind)      => { inst!(file, "{}", unop_to_inst(kind)); },
+            Inst::JmpIf(label)       => { inst!(file, "jmp_if {{_:{}}}", labels[*label]); },
+            Inst::Call(label)        => { inst!(file, "call {{_:{}}}", labels[*label]); },
+            Inst::Jmp(label)         => { cmd!(file, "scoreboard players set ip redvm.regs {}", labels[*label]); },
+            Inst::RegAdd(reg, n)     => { cmd!(file, "scoreboard players add {reg} redvm.regs {n}"); },
+            Inst::RegSub(reg, n)     => { cmd!(file, "scoreboard players remove {reg} redvm.regs {n}"); },
+            Inst::RegCopy(dst, src)  => { cmd!(file, "scoreboard players operation {dst} redvm.regs = {src} redvm.regs"); },
+            Inst::LoadExternArg(idx, arg) => {
+                cmd!(file, "execute store result storage redvm eargs.{arg} int 1 run function redvm:insts/see_local {{_:{idx}}}");
+            },
+            Inst::CallExtern(name, false) => { cmd!(file, "function {ns}:extern/{name} with storage redvm eargs"); },
+            Inst::CallExtern(name, true)  => {
+                cmd!(file, "execute store result storage redvm eret._ int 1 run function {ns}:extern/{name} with storage redvm eargs");
+            },
+            Inst::PushExternResult   => { cmd!(file, "function redvm:insts/const with storage redvm eret"); },
+            Inst::Cmd(cmd)           => { cmd!(file, "{cmd}"); },
+            Inst::LoadMacroArg(idx, arg) => {
+                cmd!(file, "execute store result storage redvm eargs.{arg} int 1 run function redvm:insts/see_local {{_:{idx}}}");
+            },
+            Inst::LoadMacroGlobal(idx, arg) => {
+                cmd!(file, "execute store result storage redvm eargs.{arg} int 1 run function redvm:insts/see_global {{_:{idx}}}");
+            },
+            Inst::LoadStrArg(idx, arg)    => { inst!(file, "load_str_local {{_:{idx},arg:{arg}}}"); },
+            Inst::LoadStrGlobal(idx, arg) => { inst!(file, "load_str_global {{_:{idx},arg:{arg}}}"); },
+            Inst::CallMacro(idx)     => { cmd!(file, "function {ns}:cmd/{idx} with storage redvm eargs"); },
+            Inst::Sleep              => { inst!(file, "sleep"); },
+        }
+    }
+}
+
+fn nbt_list(value: &[i32]) -> String {
+    let elems: Vec<String> = value.iter().map(|n| n.to_string()).collect();
+    format!("[{}]", elems.join(","))
+}
+
+pub fn log_color(level: &LogLevel) -> &'static str {
+    match level {
+        LogLevel::Debug => "gray",
+        LogLevel::Info  => "white",
+        LogLevel::Warn  => "yellow",
+        LogLevel::Error => "red",
+    }
+}
+
+fn unop_to_inst(unop: &UnOpKind) -> &'static str {
+    match unop {
+        UnOpKind::Neg    => "neg",
+        UnOpKind::Not    => "not",
+        UnOpKind::BitNot => "bnot",
+    }
+}
+
+fn binop_to_inst(binop: &BinOpKind) -> &'static str {
+    match binop {
+        BinOpKind::Add => "add",
+        BinOpKind::Sub => "sub",
+        BinOpKind::Mul => "mul",
+        BinOpKind::Div => "div",
+        BinOpKind::Mod => "mod",
+        BinOpKind::Gt  => "gt",
+        BinOpKind::Ge  => "ge",
+        BinOpKind::Lt  => "lt",
+        BinOpKind::Le  => "le",
+        BinOpKind::Eq  => "eq",
+        BinOpKind::Ne  => "ne",
+        BinOpKind::And => "and",
+        BinOpKind::Or  => "or",
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use crate::{diagnostic::Diagnostic, module::Strings, lexer::{BinOpKind, Span, UnOpKind}, parser::{cmd_parts, Ast, Block, CmdPart, Expr, ExprKind, Stmt, StmtKind}, semantic_err, vm};
+
+// Constant folding runs after the modules are linked and before the semantic
+// analysis. Names of `const`s are replaced by their values and constant
+// subexpressions are collapsed to literals with the semantics of scoreboard
+// operations. Types aren't checked yet, so operands of a wrong type are left
+// as is for the analyzer to report. Joined strings are owned by `strings`.
+pub fn fold(ast: &mut Ast, strings: &mut Strings) -> Vec<Diagnostic> {
+    let mut folder = Folder {
+        strings,
+        scopes: vec![HashMap::new()],
+        diagnostics: Vec::new(),
+    };
+    for stmt in &mut ast.stmts { folder.fold_stmt(stmt); }
+    folder.diagnostics
+}
+
+struct Folder<'a, 's> {
+    strings: &'s mut Strings,
+    // `None` for variables, they may shadow a constant
+    scopes: Vec<HashMap<&'a str, Option<ExprKind>>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Folder<'a, '_> {
+    fn get_const(&self, name: &str) -> Option<&ExprKind> {
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(name))
+            .and_then(|value| value.as_ref())
+    }
+
+    fn declare(&mut self, name: &'a str, value: Option<ExprKind>) {
+        self.scopes.last_mut().unwrap().insert(name, value);
+    }
+
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        let folded = match &mut expr.kind {
+            ExprKind::Num(_) | ExprKind::Bool(_) | ExprKind::Str(_) | ExprKind::Field(_) => None,
+            ExprKind::Var(name) => self.get_const(name).cloned(),
+            ExprKind::FnCall(data) => {
+                for arg in &mut data.args { self.fold_expr(arg); }
+                None
+            },
+            ExprKind::Array(elems) => {
+                for elem in elems { self.fold_expr(elem); }
+                None
+            },
+            ExprKind::Index(data) => {
+                self.fold_expr(&mut data.index);
+                None
+            },
+            ExprKind::Struct(data) => {
+                for (_, field) in &mut data.fields { self.fold_expr(field); }
+                None
+            },
+
+            ExprKind::UnaryOp(data) => {
+                self.fold_expr(&mut data.expr);
+                match (&data.op, &data.expr.kind) {
+                    (UnOpKind::Neg, ExprKind::Num(n)) => self.checked(n.checked_neg(), &expr.span),
+                    (UnOpKind::BitNot, ExprKind::Num(n)) => Some(ExprKind::Num(!n)),
+                    (UnOpKind::Not, ExprKind::Bool(b)) => Some(ExprKind::Bool(!b)),
+                    _ => None,
+                }
+            },
+
+            ExprKind::BinOp(data) => {
+                self.fold_expr(&mut data.lhs);
+                self.fold_expr(&mut data.rhs);
+                match (&data.lhs.kind, &data.rhs.kind) {
+                    (ExprKind::Num(a), ExprKind::Num(b)) => self.fold_int(&data.op, *a, *b, &expr.span),
+                    // string concatenation, ints are joined as their decimal text
+                    (lhs @ (ExprKind::Str(_) | ExprKind::Num(_)), rhs @ (ExprKind::Str(_) | ExprKind::Num(_))) if data.op == BinOpKind::Add => {
+                        Some(ExprKind::Str(self.strings.intern(format!("{}{}", text(lhs), text(rhs)))))
+                    },
+                    (ExprKind::Bool(a), ExprKind::Bool(b)) => match data.op {
+                        BinOpKind::And => Some(ExprKind::Bool(*a && *b)),
+                        BinOpKind::Or  => Some(ExprKind::Bool(*a || *b)),
+                        BinOpKind::Eq  => Some(ExprKind::Bool(a == b)),
+                        BinOpKind::Ne  => Some(ExprKind::Bool(a != b)),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            },
+        };
+
+        if let Some(kind) = folded {
+            expr.kind = kind;
+        }
+    }
+
+    // scores wrap around on overflow and division by zero leaves the score
+    // unchanged, neither is what a constant expression means to do
+    fn fold_int(&mut self, op: &BinOpKind, a: i32, b: i32, span: &Span) -> Option<ExprKind> {
+        match op {
+            BinOpKind::Add => self.checked(a.checked_add(b), span),
+            BinOpKind::Sub => self.checked(a.checked_sub(b), span),
+            BinOpKind::Mul => self.checked(a.checked_mul(b), span),
+            BinOpKind::Div | BinOpKind::Mod if b == 0 => {
+                self.diagnostics.push(semantic_err!(span, "Division by zero"));
+                None
+            },
+            BinOpKind::Div => self.checked(a.checked_div(b).map(|_| vm::floor_div(a, b)), span),
+            BinOpKind::Mod => Some(ExprKind::Num(vm::floor_mod(a, b))),
+            BinOpKind::Gt  => Some(ExprKind::Bool(a >  b)),
+            BinOpKind::Ge  => Some(ExprKind::Bool(a >= b)),
+            BinOpKind::Lt  => Some(ExprKind::Bool(a <  b)),
+            BinOpKind::Le  => Some(ExprKind::Bool(a <= b)),
+            BinOpKind::Eq  => Some(ExprKind::Bool(a == b)),
+            BinOpKind::Ne  => Some(ExprKind::Bool(a != b)),
+            BinOpKind::And | BinOpKind::Or => None,
+        }
+    }
+
+    fn checked(&mut self, n: Option<i32>, span: &Span) -> Option<ExprKind> {
+        if n.is_none() {
+            self.diagnostics.push(semantic_err!(span, "Integer overflow, the value doesn't fit into a score"));
+        }
+        n.map(ExprKind::Num)
+    }
+
+    fn fold_block(&mut self, block: &mut Block<'a>) {
+        self.scopes.push(HashMap::new());
+        for stmt in block { self.fold_stmt(stmt); }
+        self.scopes.pop();
+    }
+
+    fn fold_stmt(&mut self, stmt: &mut Stmt<'a>) {
+        match &mut stmt.kind {
+            StmtKind::Import(_) | StmtKind::ExternFnDecl { .. } | StmtKind::StructDecl { .. }
+            | StmtKind::Return | StmtKind::Break | StmtKind::Continue | StmtKind::Yield => {},
+
+            StmtKind::ConstDecl { name, expr } => {
+                self.fold_expr(expr);
+                let value = match expr.kind {
+                    ExprKind::Num(_) | ExprKind::Bool(_) | ExprKind::Str(_) => Some(expr.kind.clone()),
+                    _ => {
+                        self.diagnostics.push(semantic_err!(expr.span, "Value of constant `{name}` is not known at compile time"));
+                        None
+                    },
+                };
+                self.declare(name, value);
+            },
+
+            // constants become the part of the command text
+            StmtKind::BuilinFnCall { arg, .. } => {
+                let mut template = String::with_capacity(arg.len());
+                for part in cmd_parts(arg) {
+                    match part {
+                        CmdPart::Text(text) => template.push_str(text),
+                        CmdPart::Var(var) => match self.get_const(var) {
+                            Some(ExprKind::Num(n))  => template += &n.to_string(),
+                            Some(ExprKind::Bool(b)) => template += if *b { "1" } else { "0" },
+                            Some(ExprKind::Str(s))  => template += s,
+                            _ => template += &format!("{{{var}}}"),
+                        },
+                    }
+                }
+                *arg = self.strings.intern(template);
+            },
+
+            StmtKind::FnDecl(data) => {
+                self.scopes.push(data.params.iter().map(|p| (p.name, None)).collect());
+                for stmt in &mut data.body { self.fold_stmt(stmt); }
+                self.scopes.pop();
+            },
+
+            StmtKind::VarDecl(name, _) => self.declare(name, None),
+
+            StmtKind::VarDeclAssign { name, expr, .. } => {
+                self.fold_expr(expr);
+                self.declare(name, None);
+            },
+
+            StmtKind::CompoundAssign { op: BinOpKind::Div | BinOpKind::Mod, expr, .. } => {
+                self.fold_expr(expr);
+                if expr.kind == ExprKind::Num(0) {
+                    self.diagnostics.push(semantic_err!(stmt.span, "Division by zero"));
+                }
+            },
+
+            StmtKind::VarAssign { expr, .. } | StmtKind::CompoundAssign { expr, .. } | StmtKind::FieldAssign { expr, .. }
+            | StmtKind::ReturnVal(expr) | StmtKind::Sleep(expr) | StmtKind::Print(expr) => self.fold_expr(expr),
+
+            StmtKind::IndexAssign { index, expr, .. } => {
+                self.fold_expr(index);
+                self.fold_expr(expr);
+            },
+
+            StmtKind::FnCall { args, .. } | StmtKind::Log { args, .. } => {
+                for arg in args { self.fold_expr(arg); }
+            },
+
+            StmtKind::If { cond, then, elzeifs, elze } => {
+                self.fold_expr(cond);
+                self.fold_block(then);
+                for elzeif in elzeifs {
+                    self.fold_expr(&mut elzeif.cond);
+                    self.fold_block(&mut elzeif.then);
+                }
+                self.fold_block(elze);
+            },
+
+            StmtKind::For { body, init, cond, post } => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init { self.fold_stmt(init); }
+                if let Some(cond) = cond { self.fold_expr(cond); }
+                if let Some(post) = post { self.fold_stmt(post); }
+                self.fold_block(body);
+                self.scopes.pop();
+            },
+
+            StmtKind::While { cond, body } => {
+                self.fold_expr(cond);
+                self.fold_block(body);
+            },
+
+            StmtKind::Loop(body) => self.fold_block(body),
+        }
+    }
+}
+
+fn text(kind: &ExprKind) -> String {
+    match kind {
+        ExprKind::Str(s) => s.to_string(),
+        ExprKind::Num(n) => n.to_string(),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser};
+
+    // the folded strings are owned by `strings`
+    fn fold_src(src: &'static str, strings: &mut Strings) -> (Ast<'static>, Vec<String>) {
+        let mut ast = parser::parse(&mut Lexer::new(src.as_bytes(), 0)).unwrap();
+        let diagnostics = fold(&mut ast, strings).iter().map(|d| d.to_string()).collect();
+        (ast, diagnostics)
+    }
+
+    fn values(ast: &Ast) -> Vec<String> {
+        ast.stmts.iter().filter_map(|stmt| match &stmt.kind {
+            StmtKind::VarDeclAssign { expr, .. } | StmtKind::ConstDecl { expr, .. } => Some(expr.to_string()),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn constants() {
+        let mut strings = Strings::default();
+        let (ast, diagnostics) = fold_src("
+            const SIZE = 4 * 4
+            const HALF = SIZE / 2
+            a := -7 / 2 + -7 % 2
+            b := ~0 == -1 && !false
+            c := x * (2 + 3)
+            d := f(SIZE - 1, 1 < HALF)
+            e := 1 + true
+            fn main() {}
+        ", &mut strings);
+        assert_eq!(diagnostics, Vec::<String>::new());
+        assert_eq!(values(&ast), ["16", "8", "-3", "true", "[* x 5]", "f(15, true)", "[+ 1 true]"]);
+    }
+
+    #[test]
+    fn strings() {
+        let mut strings = Strings::default();
+        let (ast, diagnostics) = fold_src("
+            const NAME = \"redvm\"
+            a := \"hi \" + NAME + \"!\"
+            b := 2 * 3 + \" blocks\"
+            c := \"x\" + 1 + 2
+            d := \"n=\" + n
+            fn main() {}
+        ", &mut strings);
+        assert_eq!(diagnostics, Vec::<String>::new());
+        assert_eq!(values(&ast), ["\"redvm\"", "\"hi redvm!\"", "\"6 blocks\"", "\"x12\"", "[+ \"n=\" n]"]);
+    }
+
+    #[test]
+    fn shadowing() {
+        let mut strings = Strings::default();
+        let (ast, _) = fold_src("
+            const N = 1
+            fn main(N) {
+                x := N
+            }
+        ", &mut strings);
+        let StmtKind::FnDecl(main) = &ast.stmts[1].kind else { unreachable!() };
+        let StmtKind::VarDeclAssign { expr, .. } = &main.body[0].kind else { unreachable!() };
+        assert_eq!(expr.kind, ExprKind::Var("N"));
+    }
+
+    #[test]
+    fn errors() {
+        let mut strings = Strings::default();
+        let (_, diagnostics) = fold_src("
+            a := 2147483647 + 1
+            b := 5 % (3 - 3)
+            c := (-2147483647 - 1) / -1
+            const D = a + 1
+            fn main() {}
+        ", &mut strings);
+        assert_eq!(diagnostics, [
+            "ERROR:2:18: SemanticError: Integer overflow, the value doesn't fit into a score",
+            "ERROR:3:18: SemanticError: Division by zero",
+            "ERROR:4:18: SemanticError: Integer overflow, the value doesn't fit into a score",
+            "ERROR:5:23: SemanticError: Value of constant `D` is not known at compile time",
+        ]);
+    }
+}
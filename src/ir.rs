@@ -0,0 +1,178 @@
+use std::fmt;
+
+use crate::{lexer::{BinOpKind, UnOpKind}, parser::LogLevel, semantic::SP2};
+
+pub type Label = usize;
+
+// Every instruction except `Label` and `Fn` is exactly one redvm command
+#[derive(Debug, PartialEq, Clone)]
+pub enum Inst<'a> {
+    Fn(&'a str),
+    Label(Label),
+
+    Const(i32),
+    GetLocal(SP2),
+    SetLocal(SP2),
+    GetGlobal(usize),
+    SetGlobal(usize),
+    // `local op= pop()` without pushing the local
+    OpLocal(SP2, BinOpKind),
+    OpGlobal(usize, BinOpKind),
+    // arrays are NBT lists, elements are accessed by the index on the stack,
+    // the length is used for the bounds check
+    ArrLocal(SP2, Vec<i32>),
+    ArrGlobal(usize, Vec<i32>),
+    GetElemLocal(SP2, usize),
+    GetElemGlobal(usize, usize),
+    SetElemLocal(SP2, usize),
+    SetElemGlobal(usize, usize),
+    // strings are kept in the NBT storage under the slot of the value,
+    // the score of the slot is unused
+    StrConst(&'a str),
+    GetStrLocal(SP2),
+    SetStrLocal(SP2),
+    GetStrGlobal(usize),
+    SetStrGlobal(usize),
+    // the int on the top of the stack is replaced by its text
+    ToStr,
+    StrCat,
+    Print,
+    // the `str` on the top of the stack is shown to `redvm log_target`
+    Log(LogLevel),
+    GetReg(Reg),
+    SetReg(Reg),
+    BinOp(BinOpKind),
+    UnaryOp(UnOpKind),
+
+    Jmp(Label),
+    JmpIf(Label),
+    Call(Label),
+
+    RegAdd(Reg, usize),
+    RegSub(Reg, usize),
+    RegCopy(Reg, Reg),
+
+    LoadExternArg(SP2, usize),
+    // `true` when the returned value is saved to `redvm eret`
+    CallExtern(&'a str, bool),
+    PushExternResult,
+    Cmd(&'a str),
+
+    LoadMacroArg(SP2, usize),
+    LoadMacroGlobal(usize, usize),
+    // the text of a `str` for an extern function or a macro
+    LoadStrArg(SP2, usize),
+    LoadStrGlobal(usize, usize),
+    CallMacro(usize),
+
+    Sleep,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Reg {
+    Ip,
+    Sp,
+    Sp2,
+}
+
+#[derive(Debug, Default)]
+pub struct Program<'a> {
+    pub insts: Vec<Inst<'a>>,
+    pub label_count: usize,
+    // bodies of the macro functions for interpolated `@cmd`s
+    pub macros: Vec<String>,
+    // calls every `#[tick]` function, the pack invokes it once per game tick
+    pub tick: Option<Label>,
+    // programs with `#[load]` or `#[tick]` functions start with the datapack
+    pub autostart: bool,
+}
+
+impl<'a> Program<'a> {
+    pub fn new_label(&mut self) -> Label {
+        self.label_count += 1;
+        self.label_count-1
+    }
+
+    pub fn new_macro(&mut self, body: String) -> usize {
+        self.macros.push(body);
+        self.macros.len()-1
+    }
+
+    pub fn push(&mut self, inst: Inst<'a>) {
+        self.insts.push(inst);
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reg::Ip  => write!(f, "ip"),
+            Reg::Sp  => write!(f, "sp"),
+            Reg::Sp2 => write!(f, "sp2"),
+        }
+    }
+}
+
+impl fmt::Display for Inst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inst::Fn(name)           => write!(f, "fn {name}:"),
+            Inst::Label(label)       => write!(f, "L{label}:"),
+            Inst::Const(n)           => write!(f, "    const {n}"),
+            Inst::GetLocal(idx)      => write!(f, "    get_local {idx}"),
+            Inst::SetLocal(idx)      => write!(f, "    set_local {idx}"),
+            Inst::GetGlobal(idx)     => write!(f, "    get_global {idx}"),
+            Inst::SetGlobal(idx)     => write!(f, "    set_global {idx}"),
+            Inst::OpLocal(idx, op)   => write!(f, "    op_local {idx} {op}="),
+            Inst::OpGlobal(idx, op)  => write!(f, "    op_global {idx} {op}="),
+            Inst::ArrLocal(idx, value)  => write!(f, "    arr_local {idx} {value:?}"),
+            Inst::ArrGlobal(idx, value) => write!(f, "    arr_global {idx} {value:?}"),
+            Inst::GetElemLocal(idx, len)  => write!(f, "    get_elem_local {idx} {len}"),
+            Inst::GetElemGlobal(idx, len) => write!(f, "    get_elem_global {idx} {len}"),
+            Inst::SetElemLocal(idx, len)  => write!(f, "    set_elem_local {idx} {len}"),
+            Inst::SetElemGlobal(idx, len) => write!(f, "    set_elem_global {idx} {len}"),
+            Inst::StrConst(text)     => write!(f, "    str_const \"{text}\""),
+            Inst::GetStrLocal(idx)   => write!(f, "    get_str_local {idx}"),
+            Inst::SetStrLocal(idx)   => write!(f, "    set_str_local {idx}"),
+            Inst::GetStrGlobal(idx)  => write!(f, "    get_str_global {idx}"),
+            Inst::SetStrGlobal(idx)  => write!(f, "    set_str_global {idx}"),
+            Inst::ToStr              => write!(f, "    to_str"),
+            Inst::StrCat             => write!(f, "    str_cat"),
+            Inst::Print              => write!(f, "    print"),
+            Inst::Log(level)         => write!(f, "    log {level}"),
+            Inst::GetReg(reg)        => write!(f, "    get_reg {reg}"),
+            Inst::SetReg(reg)        => write!(f, "    set_reg {reg}"),
+            Inst::BinOp(kind)        => write!(f, "    {kind}"),
+            Inst::UnaryOp(kind)      => write!(f, "    unary {kind}"),
+            Inst::Jmp(label)         => write!(f, "    jmp L{label}"),
+            Inst::JmpIf(label)       => write!(f, "    jmp_if L{label}"),
+            Inst::Call(label)        => write!(f, "    call L{label}"),
+            Inst::RegAdd(reg, n)     => write!(f, "    {reg} += {n}"),
+            Inst::RegSub(reg, n)     => write!(f, "    {reg} -= {n}"),
+            Inst::RegCopy(dst, src)  => write!(f, "    {dst} = {src}"),
+            Inst::LoadExternArg(idx, arg) => write!(f, "    load_extern_arg {idx} {arg}"),
+            Inst::CallExtern(name, false) => write!(f, "    call_extern {name}"),
+            Inst::CallExtern(name, true)  => write!(f, "    call_extern {name} -> eret"),
+            Inst::PushExternResult   => write!(f, "    push_extern_result"),
+            Inst::Cmd(cmd)           => write!(f, "    cmd \"{cmd}\""),
+            Inst::LoadMacroArg(idx, arg) => write!(f, "    load_macro_arg {idx} {arg}"),
+            Inst::LoadMacroGlobal(idx, arg) => write!(f, "    load_macro_global {idx} {arg}"),
+            Inst::LoadStrArg(idx, arg)    => write!(f, "    load_str_arg {idx} {arg}"),
+            Inst::LoadStrGlobal(idx, arg) => write!(f, "    load_str_global {idx} {arg}"),
+            Inst::CallMacro(idx)     => write!(f, "    call_macro {idx}"),
+            Inst::Sleep              => write!(f, "    sleep"),
+        }
+    }
+}
+
+impl fmt::Display for Program<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for inst in &self.insts {
+            writeln!(f, "{inst}")?;
+        }
+        for (idx, body) in self.macros.iter().enumerate() {
+            writeln!(f, "macro {idx}: {body}")?;
+        }
+        Ok(())
+    }
+}
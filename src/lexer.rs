@@ -1,7 +1,7 @@
-use super::*;
-
 use std::mem::transmute;
 
+use crate::{diagnostic::Diagnostic, lexical_err, syntax_err};
+
 
 
 // TODO: think about this way of lexering
@@ -30,9 +30,23 @@ pub struct Loc {
     pub col: usize
 }
 
+// index of the source file in `module::Loader::sources`
+pub type FileId = usize;
+
+// `end` points right after the last character
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub file:  FileId,
+    pub start: Loc,
+    pub end:   Loc,
+}
+
 #[derive(Debug)]
 pub struct Lexer<'a> {
     pub loc: Loc,
+    file: FileId,
+    token_end: Loc,
+    prev_end: Loc,
     src: &'a [u8],
     pos: usize,
     curr_token_len: usize,
@@ -56,6 +70,14 @@ pub enum BinOpKind {
     Or,
 }
 
+// `-` is lexed as `BinOpKind::Sub`, the parser decides which one it is
+#[derive(Debug, PartialEq, Clone)]
+pub enum UnOpKind {
+    Neg,
+    Not,
+    BitNot,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Keyword {
     If,
@@ -63,10 +85,21 @@ pub enum Keyword {
     Fn,
     Return,
     For,
+    While,
+    Loop,
     Int,
+    Bool,
+    True,
+    False,
     Break,
     Continue,
     Extern,
+    Import,
+    Yield,
+    Sleep,
+    Const,
+    Struct,
+    Str,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -77,9 +110,18 @@ pub enum Punct {
     CloseParen,
     OpenCurly,
     CloseCurly,
+    OpenBracket,
+    CloseBracket,
     Colon,
+    Dot,
+    PathSep,
+    Arrow,
     Eq,
-    At
+    Incr,
+    Decr,
+    At,
+    // `#[` of `#[tick]`, any other `#` starts a comment
+    Attr,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -88,6 +130,9 @@ pub enum Token {
     StrLit(&'static str),
     Number(i32),
     BinOp(BinOpKind),
+    // `+=`, `-=`, `*=`, `/=` and `%=`
+    AssignOp(BinOpKind),
+    UnOp(UnOpKind),
     Keyword(Keyword),
     Punct(Punct),
 }
@@ -99,80 +144,94 @@ impl<'a> Lexer<'a> {
         ("return",   Keyword::Return),
         ("else",     Keyword::Else),
         ("for",      Keyword::For),
+        ("while",    Keyword::While),
+        ("loop",     Keyword::Loop),
         ("int",      Keyword::Int),
+        ("bool",     Keyword::Bool),
+        ("true",     Keyword::True),
+        ("false",    Keyword::False),
         ("break",    Keyword::Break),
         ("continue", Keyword::Continue),
         ("extern",   Keyword::Extern),
+        ("import",   Keyword::Import),
+        ("yield",    Keyword::Yield),
+        ("sleep",    Keyword::Sleep),
+        ("const",    Keyword::Const),
+        ("struct",   Keyword::Struct),
+        ("str",      Keyword::Str),
     ];
 
-    pub fn new(src: &'a [u8]) -> Self {
+    pub fn new(src: &'a [u8], file: FileId) -> Self {
         Self {
             src,
+            file,
             pos: 0,
             loc: Loc { row: 1, col: 1 },
+            token_end: Loc { row: 1, col: 1 },
+            prev_end: Loc { row: 1, col: 1 },
             curr_token_len: 0,
             peeked: None,
         }
     }
 
 
-    fn ident(&mut self) -> Option<&'static str> {
-        if !self.src[self.pos].is_ascii_alphabetic() { return None; }
+    fn ident(&mut self) -> Result<Option<&'static str>, Diagnostic> {
+        if !self.src[self.pos].is_ascii_alphabetic() { return Ok(None); }
         let mut end: usize = self.pos+1;
         while end < self.src.len() &&
             self.src[end].is_ascii_alphanumeric() { end += 1; }
-        let text = self.str_from_range(self.pos..end);
+        let text = self.str_from_range(self.pos..end)?;
         self.curr_token_len = text.len();
-        Some(text)
+        Ok(Some(text))
     }
 
-    fn strlit(&mut self) -> Option<&'static str> {
-        if self.src[self.pos] != b'"' { return None; }
+    fn strlit(&mut self) -> Result<Option<&'static str>, Diagnostic> {
+        if self.src[self.pos] != b'"' { return Ok(None); }
         let mut end: usize = self.pos+1;
         while self.src[end] != b'"' {
             end += 1;
             if end == self.src.len() {
-                lexical_err!(self.loc, "Unclosed string literal");
+                return Err(lexical_err!(self.span_to(self.pos+1), "Unclosed string literal"));
             }
         }
-        let text = self.str_from_range(self.pos+1..end);
+        let text = self.str_from_range(self.pos+1..end)?;
         self.curr_token_len = text.len()+2;
-        Some(text)
+        Ok(Some(text))
     }
 
-    fn keyword(&mut self) -> Option<Keyword> {
+    fn keyword(&mut self) -> Result<Option<Keyword>, Diagnostic> {
         let mut end: usize = self.pos+1;
         while end < self.src.len() &&
             self.src[end].is_ascii_alphabetic() {
                 end += 1;
         }
 
-        let text = self.str_from_range(self.pos..end);
+        let text = self.str_from_range(self.pos..end)?;
         for (keyword, kind) in Self::KEYWORDS {
             if text == *keyword {
                 self.curr_token_len = text.len();
-                return Some(kind.clone());
+                return Ok(Some(kind.clone()));
             }
         }
 
-        None
+        Ok(None)
     }
 
-    fn number(&mut self) -> Option<i32> {
-        if !self.src[self.pos].is_ascii_digit() { return None; }
+    fn number(&mut self) -> Result<Option<i32>, Diagnostic> {
+        if !self.src[self.pos].is_ascii_digit() { return Ok(None); }
         let mut end: usize = self.pos+1;
         while end < self.src.len() &&
             self.src[end].is_ascii_digit() {
                 end += 1;
         }
 
-        match self.str_from_range(self.pos..end).parse::<i32>() {
+        match self.str_from_range(self.pos..end)?.parse::<i32>() {
             Ok(num) => {
                 self.curr_token_len = end - self.pos;
-                Some(num)
+                Ok(Some(num))
             },
             Err(_) => {
-                lexical_err!(self.loc, "Invalid 32-bit integer");
+                Err(lexical_err!(self.span_to(end), "Invalid 32-bit integer"))
             }
         }
     }
@@ -186,17 +245,48 @@ impl<'a> Lexer<'a> {
             b')' => Punct::CloseParen,
             b'{' => Punct::OpenCurly,
             b'}' => Punct::CloseCurly,
+            b'[' => Punct::OpenBracket,
+            b']' => Punct::CloseBracket,
+            b':' if self.src.get(self.pos+1) == Some(&b':') => {
+                self.curr_token_len = 2;
+                return Some(Punct::PathSep);
+            },
             b':' => Punct::Colon,
+            b'.' => Punct::Dot,
+            b'-' if self.src.get(self.pos+1) == Some(&b'>') => {
+                self.curr_token_len = 2;
+                return Some(Punct::Arrow);
+            },
             b'@' => Punct::At,
+            b'#' if self.src.get(self.pos+1) == Some(&b'[') => {
+                self.curr_token_len = 2;
+                return Some(Punct::Attr);
+            },
             _ => return None
         };
         self.curr_token_len = 1;
         Some(punct)
     }
 
+    fn assign_op(&mut self) -> Option<Token> {
+        let token = match (self.src[self.pos], self.src.get(self.pos+1)) {
+            (b'+', Some(b'+')) => Token::Punct(Punct::Incr),
+            (b'-', Some(b'-')) => Token::Punct(Punct::Decr),
+            (b'+', Some(b'=')) => Token::AssignOp(BinOpKind::Add),
+            (b'-', Some(b'=')) => Token::AssignOp(BinOpKind::Sub),
+            (b'*', Some(b'=')) => Token::AssignOp(BinOpKind::Mul),
+            (b'/', Some(b'=')) => Token::AssignOp(BinOpKind::Div),
+            (b'%', Some(b'=')) => Token::AssignOp(BinOpKind::Mod),
+            _ => return None
+        };
+        self.curr_token_len = 2;
+        Some(token)
+    }
+
     fn bin_op(&mut self) -> Option<BinOpKind> {
         let bin_op_kind = match self.src[self.pos] {
             b'+' => { self.curr_token_len = 1; BinOpKind::Add },
+            b'-' if self.src.get(self.pos+1) == Some(&b'>') => return None,
             b'-' => { self.curr_token_len = 1; BinOpKind::Sub },
             b'*' => { self.curr_token_len = 1; BinOpKind::Mul },
             b'/' => { self.curr_token_len = 1; BinOpKind::Div },
@@ -229,92 +319,143 @@ impl<'a> Lexer<'a> {
         Some(bin_op_kind)
     }
 
-    pub fn expect_ident(&mut self) -> &'static str {
+    fn un_op(&mut self) -> Option<UnOpKind> {
+        let un_op_kind = match self.src[self.pos] {
+            b'!' => UnOpKind::Not,
+            b'~' => UnOpKind::BitNot,
+            _ => return None
+        };
+        self.curr_token_len = 1;
+        Some(un_op_kind)
+    }
+
+    pub fn expect_ident(&mut self) -> Result<&'static str, Diagnostic> {
         self.pos += self.curr_token_len;
         self.loc.col += self.curr_token_len;
         if !self.skip_whitespace_and_comments() {
-            if let Some(text) = self.ident() {
-                return text;
+            if let Some(text) = self.ident()? {
+                self.token_end = self.span_to(self.pos+text.len()).end;
+                self.prev_end = self.token_end.clone();
+                return Ok(text);
             }
         }
 
-        syntax_err!(self.loc, "Identifier was expected, but it did not appear");
+        Err(syntax_err!(self.span_to(self.pos+1), "Identifier was expected, but it did not appear"))
     }
 
-    pub fn expect_punct(&mut self, expected: Punct) {
-        let p = self.expect_any();
+    pub fn expect_punct(&mut self, expected: Punct) -> Result<(), Diagnostic> {
+        let p = self.expect_any()?;
         if p != Token::Punct(expected.clone()) {
-            syntax_err!(self.loc, "Punctuator `{expected}` was expected, but found `{p}`");
+            return Err(syntax_err!(self.span(), "Punctuator `{expected}` was expected, but found `{p}`"));
         }
+        Ok(())
     }
 
-    pub fn next_any(&mut self) -> Option<Token> {
+    pub fn next_any(&mut self) -> Result<Option<Token>, Diagnostic> {
         let result = self.peek_any();
         self.peeked = None;
+        self.prev_end = self.token_end.clone();
         result
     }
 
-    pub fn peek_any(&mut self) -> Option<Token> {
+    pub fn peek_any(&mut self) -> Result<Option<Token>, Diagnostic> {
         if self.peeked.is_some() {
-            return self.peeked.clone();
+            return Ok(self.peeked.clone());
         }
         
         self.pos += self.curr_token_len;
         self.loc.col += self.curr_token_len;
         if self.skip_whitespace_and_comments() {
             self.curr_token_len = 0;
-            return None;
+            return Ok(None);
         }
 
-        let result =
-            self.bin_op().map(|op| Token::BinOp(op))
-            .or_else(|| self.strlit().map(|p| Token::StrLit(p)))
-            .or_else(|| self.punct().map(|p| Token::Punct(p)))
-            .or_else(|| self.keyword().map(|k| Token::Keyword(k)))
-            .or_else(|| self.number().map(|n| Token::Number(n)))
-            .or_else(|| self.ident().map(|i| Token::Ident(i)));
-
-        if result.is_none() {
-            lexical_err!(self.loc, "Undefined token");
-        }
+        let result = if let Some(token) = self.assign_op() {
+            token
+        } else if let Some(op) = self.bin_op() {
+            Token::BinOp(op)
+        } else if let Some(op) = self.un_op() {
+            Token::UnOp(op)
+        } else if let Some(lit) = self.strlit()? {
+            Token::StrLit(lit)
+        } else if let Some(p) = self.punct() {
+            Token::Punct(p)
+        } else if let Some(k) = self.keyword()? {
+            Token::Keyword(k)
+        } else if let Some(n) = self.number()? {
+            Token::Number(n)
+        } else if let Some(i) = self.ident()? {
+            Token::Ident(i)
+        } else {
+            return Err(lexical_err!(self.span_to(self.pos+1), "Undefined token"));
+        };
 
-        self.peeked = result.clone();
+        self.token_end = self.span_to(self.pos+self.curr_token_len).end;
+        self.peeked = Some(result.clone());
 
-        result
+        Ok(Some(result))
     }
 
-    pub fn expect_peek_any(&mut self) -> Token {
-        self.peek_any().unwrap_or_else(|| {
-            syntax_err!(self.loc, "Token was expected, but reached the end");
+    pub fn expect_peek_any(&mut self) -> Result<Token, Diagnostic> {
+        self.peek_any()?.ok_or_else(|| {
+            syntax_err!(self.span_to(self.pos), "Token was expected, but reached the end")
         })
     }
 
-    pub fn expect_any(&mut self) -> Token {
-        self.next_any().unwrap_or_else(|| {
-            syntax_err!(self.loc, "Token was expected, but reached the end");
+    pub fn expect_any(&mut self) -> Result<Token, Diagnostic> {
+        self.next_any()?.ok_or_else(|| {
+            syntax_err!(self.span_to(self.pos), "Token was expected, but reached the end")
         })
     }
 
-    fn str_from_range(&self, range: std::ops::Range<usize>) -> &'static str {
-        unsafe {
-            transmute::<&str, &'static str>(
-                std::str::from_utf8(&self.src[range])
-                    .unwrap_or_else(|_| {
-                        lexical_err!(self.loc, "Invalid UTF-8");
-                    })
-            )
+    fn str_from_range(&self, range: std::ops::Range<usize>) -> Result<&'static str, Diagnostic> {
+        match std::str::from_utf8(&self.src[range]) {
+            Ok(text) => Ok(unsafe { transmute::<&str, &'static str>(text) }),
+            Err(_) => Err(lexical_err!(self.span_to(self.pos+1), "Invalid UTF-8")),
+        }
+    }
+
+    // `module::name` as one slice of the source, `None` if the parts
+    // are separated by whitespace
+    pub fn path(&self, module: &'static str, item: &'static str) -> Option<&'static str> {
+        let start = module.as_ptr() as usize - self.src.as_ptr() as usize;
+        let item_start = item.as_ptr() as usize - self.src.as_ptr() as usize;
+        if start + module.len() + 2 != item_start { return None; }
+        self.str_from_range(start..item_start+item.len()).ok()
+    }
+
+    // span of the current (peeked or the last consumed) token
+    pub fn span(&self) -> Span {
+        Span { file: self.file, start: self.loc.clone(), end: self.token_end.clone() }
+    }
+
+    // span from `start` to the end of the last consumed token
+    pub fn span_from(&self, start: Loc) -> Span {
+        Span { file: self.file, start, end: self.prev_end.clone() }
+    }
+
+    // span from the current location to the byte position `end` on the same line
+    fn span_to(&self, end: usize) -> Span {
+        let len = end.saturating_sub(self.pos);
+        Span {
+            file: self.file,
+            start: self.loc.clone(),
+            end: Loc { row: self.loc.row, col: self.loc.col + len },
         }
     }
 
+    fn is_comment(&self) -> bool {
+        self.src[self.pos] == b'#' && self.src.get(self.pos+1) != Some(&b'[')
+    }
+
     // returns true if the end is reached
     fn skip_whitespace_and_comments(&mut self) -> bool {
         if self.pos >= self.src.len() { return true; }
-        while self.src[self.pos].is_ascii_whitespace() ||
-              self.src[self.pos] == b'#' {
+        while self.src[self.pos].is_ascii_whitespace() || self.is_comment() {
             if self.src[self.pos] == b'\n' {
                 self.loc.row += 1;
                 self.loc.col = 1;
-            } else if self.src[self.pos] == b'#'  {
+            } else if self.is_comment() {
                 self.loc.row += 1;
                 self.loc.col = 1;
                 while self.src[self.pos] != b'\n' {
@@ -337,18 +478,38 @@ impl fmt::Display for Loc {
     }
 }
 
+impl Span {
+    pub fn to(&self, other: &Span) -> Span {
+        Span { file: self.file, start: self.start.clone(), end: other.end.clone() }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.start)
+    }
+}
+
 impl fmt::Display for Punct {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
          match self {
             Punct::Comma      => write!(f, ","),
             Punct::Semicolon  => write!(f, ";"),
             Punct::Colon      => write!(f, ":"),
+            Punct::Dot        => write!(f, "."),
+            Punct::PathSep    => write!(f, "::"),
+            Punct::Arrow      => write!(f, "->"),
             Punct::Eq         => write!(f, "="),
+            Punct::Incr       => write!(f, "++"),
+            Punct::Decr       => write!(f, "--"),
             Punct::OpenParen  => write!(f, "("),
             Punct::CloseParen => write!(f, ")"),
             Punct::OpenCurly  => write!(f, "{{"),
             Punct::CloseCurly => write!(f, "}}"),
+            Punct::OpenBracket  => write!(f, "["),
+            Punct::CloseBracket => write!(f, "]"),
             Punct::At         => write!(f, "@"),
+            Punct::Attr       => write!(f, "#["),
         }
     }
 }
@@ -373,6 +534,16 @@ impl fmt::Display for BinOpKind {
     }
 }
 
+impl fmt::Display for UnOpKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnOpKind::Neg    => write!(f, "-"),
+            UnOpKind::Not    => write!(f, "!"),
+            UnOpKind::BitNot => write!(f, "~"),
+        }
+    }
+}
+
 impl fmt::Display for Keyword {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -381,10 +552,21 @@ impl fmt::Display for Keyword {
             Keyword::Fn       => write!(f, "fn"),
             Keyword::Return   => write!(f, "return"),
             Keyword::For      => write!(f, "for"),
+            Keyword::While    => write!(f, "while"),
+            Keyword::Loop     => write!(f, "loop"),
             Keyword::Int      => write!(f, "int"),
+            Keyword::Bool     => write!(f, "bool"),
+            Keyword::True     => write!(f, "true"),
+            Keyword::False    => write!(f, "false"),
             Keyword::Break    => write!(f, "break"),
             Keyword::Continue => write!(f, "continue"),
             Keyword::Extern   => write!(f, "extern"),
+            Keyword::Import   => write!(f, "import"),
+            Keyword::Yield    => write!(f, "yield"),
+            Keyword::Sleep    => write!(f, "sleep"),
+            Keyword::Const    => write!(f, "const"),
+            Keyword::Struct   => write!(f, "struct"),
+            Keyword::Str      => write!(f, "str"),
         }
     }
 }
@@ -396,6 +578,8 @@ impl fmt::Display for Token {
             Token::StrLit(text)  => write!(f, "string literal `{text}`"),
             Token::Number(num)   => write!(f, "number `{num}`"),
             Token::BinOp(kind)   => write!(f, "binary operation `{kind}`"),
+            Token::AssignOp(kind) => write!(f, "assignment operation `{kind}=`"),
+            Token::UnOp(kind)    => write!(f, "unary operation `{kind}`"),
             Token::Keyword(kind) => write!(f, "keyword `{kind}`"),
             Token::Punct(kind)   => write!(f, "punctuator `{kind}`"),
         }
@@ -405,6 +589,7 @@ impl fmt::Display for Token {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::unexpected_token_err;
 
     const SOURCE: &[u8] = "
         num1 = 324;\n\t\
@@ -415,51 +600,95 @@ mod tests {
     ".as_bytes();
 
     #[test]
-    #[should_panic]
     fn unexpected_token() {
-        let mut lexer = Lexer::new(SOURCE);
-        match lexer.expect_any() {
-            Token::Ident(_) => {},
-            t @ _ => { unexpected_token_err!(lexer.loc, t); }
-        }
-        match lexer.expect_any() {
-            Token::Punct(Punct::Semicolon) => {},
-            t @ _ => { unexpected_token_err!(lexer.loc, t); }
-        }
+        let mut lexer = Lexer::new(SOURCE, 0);
+        assert!(matches!(lexer.expect_any(), Ok(Token::Ident(_))));
+        let err = match lexer.expect_any().unwrap() {
+            Token::Punct(Punct::Semicolon) => None,
+            t => Some(unexpected_token_err!(lexer.span(), t)),
+        };
+        assert_eq!(err.unwrap().message, "Unexpected punctuator `=`");
     }
 
     #[test]
-    #[should_panic]
     fn expect_punct() {
-        let mut lexer = Lexer::new(SOURCE);
-        lexer.expect_punct(Punct::Eq);
+        let mut lexer = Lexer::new(SOURCE, 0);
+        assert!(lexer.expect_punct(Punct::Eq).is_err());
     }
 
     #[test]
-    #[should_panic]
     fn expect_ident() {
-        let mut lexer = Lexer::new(SOURCE);
-        let _ = lexer.expect_ident();
-        let _ = lexer.expect_ident();
+        let mut lexer = Lexer::new(SOURCE, 0);
+        assert!(lexer.expect_ident().is_ok());
+        assert!(lexer.expect_ident().is_err());
+    }
+
+    #[test]
+    fn assign_ops() {
+        let mut lexer = Lexer::new(b"x += 1; i++ j-- a - -b %= c", 0);
+        let mut tokens = Vec::new();
+        while let Some(token) = lexer.next_any().unwrap() {
+            tokens.push(token);
+        }
+        assert_eq!(tokens, [
+            Token::Ident("x"),
+            Token::AssignOp(BinOpKind::Add),
+            Token::Number(1),
+            Token::Punct(Punct::Semicolon),
+            Token::Ident("i"),
+            Token::Punct(Punct::Incr),
+            Token::Ident("j"),
+            Token::Punct(Punct::Decr),
+            Token::Ident("a"),
+            Token::BinOp(BinOpKind::Sub),
+            Token::BinOp(BinOpKind::Sub),
+            Token::Ident("b"),
+            Token::AssignOp(BinOpKind::Mod),
+            Token::Ident("c"),
+        ]);
+    }
+
+    #[test]
+    fn attributes() {
+        let mut lexer = Lexer::new(b"# comment\n#[tick] fn", 0);
+        let mut tokens = Vec::new();
+        while let Some(token) = lexer.next_any().unwrap() {
+            tokens.push(token);
+        }
+        assert_eq!(tokens, [
+            Token::Punct(Punct::Attr),
+            Token::Ident("tick"),
+            Token::Punct(Punct::CloseBracket),
+            Token::Keyword(Keyword::Fn),
+        ]);
+    }
+
+    #[test]
+    fn path() {
+        let mut lexer = Lexer::new(b"geom::dist geom :: dist", 0);
+        let mut idents = Vec::new();
+        while let Some(token) = lexer.next_any().unwrap() {
+            if let Token::Ident(name) = token { idents.push(name); }
+        }
+        assert_eq!(lexer.path(idents[0], idents[1]), Some("geom::dist"));
+        assert_eq!(lexer.path(idents[2], idents[3]), None);
     }
 
     #[test]
-    #[should_panic]
     fn illegal_int() {
-        let mut lexer = Lexer::new(b"123412341234123412341234123412341234");
-        let _ = lexer.expect_any();
+        let mut lexer = Lexer::new(b"123412341234123412341234123412341234", 0);
+        assert_eq!(lexer.expect_any().unwrap_err().message, "Invalid 32-bit integer");
     }
 
     #[test]
-    #[should_panic]
     fn illegal_utf8() {
-        let mut lexer = Lexer::new(b"\xE0");
-        let _ = lexer.expect_any();
+        let mut lexer = Lexer::new(b"\xE0", 0);
+        assert_eq!(lexer.expect_any().unwrap_err().message, "Invalid UTF-8");
     }
 
     #[test]
     fn peek() {
-        let mut lexer = Lexer::new(SOURCE);
+        let mut lexer = Lexer::new(SOURCE, 0);
         let t1 = lexer.peek_any();
         let t2 = lexer.peek_any();
         let t3 = lexer.next_any();
@@ -471,7 +700,7 @@ mod tests {
 
     #[test]
     fn test_next() {
-        let mut lexer = Lexer::new(SOURCE);
+        let mut lexer = Lexer::new(SOURCE, 0);
         let expected = [
             Token::Ident("num1"),
             Token::Punct(Punct::Eq),
@@ -499,7 +728,7 @@ mod tests {
         ];
 
         for (i, x) in expected.iter().enumerate() {
-            let token = lexer.expect_any();
+            let token = lexer.expect_any().unwrap();
             assert_eq!(token, x.clone(), "{i}");
         }
     }
@@ -1,7 +1,16 @@
+mod cli;
+mod diagnostic;
 mod lexer;
+mod module;
 mod parser;
 mod compiler;
+mod datapack;
+mod emitter;
+mod fold;
+mod ir;
+mod native;
 mod semantic;
+mod vm;
 
 use std::io::prelude::*;
 
@@ -15,37 +24,40 @@ macro_rules! exit_failure { () => { panic!(); } }
 #[macro_export]
 macro_rules! exit_failure { () => { std::process::exit(1); } }
 
+// `lexical_err!`, `syntax_err!` and `semantic_err!` build a `Diagnostic`,
+// the caller decides whether to return it or to keep going
 #[macro_export]
 macro_rules! lexical_err {
-    ($loc:expr, $($arg:tt)*) => {
-        eprint!("ERROR:{}: LexicalError: ", $loc);
-        eprintln!($($arg)*);
-        exit_failure!();
+    ($span:expr, $($arg:tt)*) => {
+        $crate::diagnostic::Diagnostic::error(Some($span.clone()), "LexicalError", format!($($arg)*))
     }
 }
 
 #[macro_export]
 macro_rules! syntax_err {
-    ($loc:expr, $($arg:tt)*) => {
-        eprint!("ERROR:{}: SyntaxError: ", $loc);
-        eprintln!($($arg)*);
-        exit_failure!();
+    ($span:expr, $($arg:tt)*) => {
+        $crate::diagnostic::Diagnostic::error(Some($span.clone()), "SyntaxError", format!($($arg)*))
     }
 }
 
 #[macro_export]
 macro_rules! unexpected_token_err {
-    ($loc:expr, $t:ident) => {
-        syntax_err!($loc, "Unexpected {}", $t);
+    ($span:expr, $t:ident) => {
+        syntax_err!($span, "Unexpected {}", $t)
     }
 }
 
 #[macro_export]
 macro_rules! semantic_err {
-    ($loc:expr, $($arg:tt)*) => {
-        eprint!("ERROR:{}: SemanticError: ", $loc);
-        eprintln!($($arg)*);
-        exit_failure!();
+    ($span:expr, $($arg:tt)*) => {
+        $crate::diagnostic::Diagnostic::error(Some($span.clone()), "SemanticError", format!($($arg)*))
+    }
+}
+
+#[macro_export]
+macro_rules! semantic_warn {
+    ($span:expr, $($arg:tt)*) => {
+        $crate::diagnostic::Diagnostic::warning(Some($span.clone()), "SemanticWarning", format!($($arg)*))
     }
 }
 
@@ -58,6 +70,15 @@ macro_rules! compilation_err {
     }
 }
 
+#[macro_export]
+macro_rules! runtime_err {
+    ($($arg:tt)*) => {
+        eprint!("ERROR: RuntimeError: ");
+        eprintln!($($arg)*);
+        exit_failure!();
+    }
+}
+
 macro_rules! error {
     ($($arg:tt)*) => {
         eprint!("ERROR: ");
@@ -66,12 +87,8 @@ macro_rules! error {
     };
 }
 
-fn main() {
-    let file_path = std::env::args().nth(1).unwrap_or_else(|| {
-        error!("Source file must be provided");
-    });
-
-    let mut src_file = std::fs::File::open(&file_path).unwrap_or_else(|err| {
+fn read_file(file_path: &str) -> String {
+    let mut src_file = std::fs::File::open(file_path).unwrap_or_else(|err| {
         error!("Could not open file `{file_path}`: {err}");
     });
 
@@ -80,19 +97,139 @@ fn main() {
         error!("Could not read file `{file_path}`: {err}");
     });
 
-    let output = std::fs::File::create("out.mcfunction").unwrap_or_else(|err| {
-        error!("Could not create an output file: {err}");
-    });
+    buffer
+}
+
+// `data/<ns>/function/program.mcfunction` of a redvm datapack has its
+// `@cmd` macro functions in `data/<ns>/function/cmd/`
+fn program_macros(file_path: &str) -> Vec<(String, String)> {
+    let dir = std::path::Path::new(file_path).parent().unwrap_or(std::path::Path::new(""));
+    let Some(ns) = dir.parent().and_then(|p| p.file_name()).map(|ns| ns.to_string_lossy()) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(dir.join("cmd")) else {
+        return Vec::new();
+    };
+
+    let mut macros = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if let Some(idx) = path.file_name().and_then(|f| f.to_str()).and_then(|f| f.strip_suffix(".mcfunction")) {
+            macros.push((format!("{ns}:cmd/{idx}"), read_file(&path.to_string_lossy())));
+        }
+    }
+    macros
+}
 
-    let mut lexer = lexer::Lexer::new(buffer.as_bytes()); // lexical analysis (lazy)
+fn main() {
+    let args = cli::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}", cli::USAGE);
+        error!("{err}");
+    });
 
-    let ast: Ast = parser::parse(&mut lexer);             // syntax  analysis
-    //println!("{ast:#?}");
+    let file_path = args.file_path.as_str();
+    match args.command {
+        cli::Command::Help => {
+            println!("{}", cli::USAGE);
+            return;
+        },
+
+        // compiled programs are executed as is
+        cli::Command::Run if file_path.ends_with(".mcfunction") => {
+            let vm = vm::Vm::load(&read_file(file_path))
+                .with_functions(program_macros(file_path))
+                .with_max_steps(args.max_steps)
+                .with_max_stack(args.pack.max_stack)
+                .with_insts_per_tick(args.pack.insts_per_tick)
+                .with_extern_results(args.externs);
+            for output in vm.run() {
+                println!("{output}");
+            }
+            return;
+        },
+
+        _ => {},
+    }
 
-    //println!("===========================================");
+    let mut loader = module::Loader::default();
+    let mut ast: Ast = loader.load(file_path)                 // lexical and syntax analysis of every module
+        .unwrap_or_else(|diagnostic| {
+            if loader.sources.is_empty() {
+                error!("{}", diagnostic.message);
+            }
+            eprint!("{}", loader.render(&diagnostic));
+            exit_failure!();
+        });
+
+    if args.command == cli::Command::DumpAst {
+        println!("{ast:#?}");
+        return;
+    }
 
-    let scopes = semantic::Analyzer::analyze(&ast);     // semantic analyzis
-    //println!("{scopes:#?}");
+    let mut diagnostics = fold::fold(&mut ast, &mut loader.strings); // constant folding
+    let (scopes, semantic) = semantic::Analyzer::analyze(&ast);   // semantic analyzis
+    diagnostics.extend(semantic);
+    for diagnostic in &diagnostics {
+        eprint!("{}", loader.render(diagnostic));
+    }
+    if diagnostics.iter().any(|d| d.is_error()) {
+        exit_failure!();
+    }
 
-    compiler::compile(output, &ast, scopes);
+    match args.command {
+        cli::Command::Check => {},
+
+        cli::Command::DumpIr => {
+            print!("{}", compiler::compile(&ast, scopes, args.pack.log_level));
+        },
+
+        cli::Command::Run => {
+            let ns = &args.pack.namespace;
+            let program = compiler::compile(&ast, scopes, args.pack.log_level);
+            let mut text = Vec::new();
+            emitter::emit(&mut text, &program, ns);
+            let vm = vm::Vm::load(&String::from_utf8_lossy(&text))
+                .with_functions(emitter::macros(&program, ns))
+                .with_tick_entry(emitter::tick_ip(&program))
+                .with_ticks(args.ticks)
+                .with_max_steps(args.max_steps)
+                .with_max_stack(args.pack.max_stack)
+                .with_insts_per_tick(args.pack.insts_per_tick)
+                .with_extern_results(args.externs);
+            for output in vm.run() {
+                println!("{output}");
+            }
+        },
+
+        cli::Command::Build => {
+            let output_path = std::path::Path::new(args.output.as_deref().unwrap_or("out"));
+            let files = if args.redvm {
+                let ns = &args.pack.namespace;
+                let program = compiler::compile(&ast, scopes, args.pack.log_level);        // intermediate representation
+                let mut text = Vec::new();
+                emitter::emit(&mut text, &program, ns);
+                let text = String::from_utf8(text).unwrap();
+
+                // a bare program for custom vms
+                if output_path.extension().is_some_and(|ext| ext == "mcfunction") {
+                    if !program.macros.is_empty() {
+                        error!("`@cmd` with variables needs macro functions, use a datapack output");
+                    }
+                    if program.tick.is_some() {
+                        error!("`#[tick]` functions are invoked by `#minecraft:tick`, use a datapack output");
+                    }
+                    std::fs::write(output_path, text).unwrap_or_else(|err| {
+                        error!("Could not write `{}`: {err}", output_path.display());
+                    });
+                    return;
+                }
+                datapack::redvm(text, emitter::macros(&program, ns), emitter::tick_ip(&program), program.autostart, &args.pack)
+            } else {
+                native::compile(&ast, scopes, &args.pack)
+            };
+            datapack::write(output_path, &files);
+        },
+
+        cli::Command::Help | cli::Command::DumpAst => unreachable!(),
+    }
 }
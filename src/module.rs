@@ -0,0 +1,440 @@
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}};
+
+use crate::{diagnostic::Diagnostic, lexer::{Lexer, Span}, parser::{self, cmd_parts, Ast, Block, CmdPart, DataType, Expr, ExprKind, Stmt, StmtKind}, semantic_err};
+
+// Module system. `import "path/to/geom.luba"` loads the file as the module
+// `geom`. Every module item (functions and global variables) is renamed to
+// `geom::<name>`, so all modules are linked into one flat `Ast` and the later
+// stages never see two items with the same name. The root module keeps its
+// names as is. Imported modules come before the importer, so their functions
+// are declared before they are called.
+
+pub struct Source {
+    pub path: String,
+    pub text: &'static str,
+}
+
+enum State {
+    Loading,
+    Loaded,
+}
+
+// Owns the source texts and the names and strings built after lexing
+// (qualified names, command templates, folded strings). Like the slices of
+// the lexer they are handed out as `&'static str`, so the `Ast` must not
+// outlive the `Loader` that owns them.
+#[derive(Default)]
+pub struct Strings(HashSet<Box<str>>);
+
+impl Strings {
+    pub fn intern(&mut self, s: String) -> &'static str {
+        let text = s.into_boxed_str();
+        let ptr: *const str = match self.0.get(&text) {
+            Some(text) => &**text,
+            None => {
+                let ptr: *const str = &*text;
+                self.0.insert(text);
+                ptr
+            },
+        };
+        // the boxed text doesn't move when the set grows
+        unsafe { &*ptr }
+    }
+}
+
+#[derive(Default)]
+pub struct Loader {
+    pub sources: Vec<Source>,
+    pub strings: Strings,
+    modules: HashMap<PathBuf, State>,
+    names: HashMap<&'static str, PathBuf>,
+    externs: HashMap<&'static str, (Vec<DataType>, Option<DataType>)>,
+    stmts: Vec<Stmt<'static>>,
+}
+
+impl Loader {
+    pub fn load(&mut self, path: &str) -> Result<Ast<'static>, Diagnostic> {
+        self.load_module(Path::new(path), None, None)?;
+        Ok(Ast { stmts: std::mem::take(&mut self.stmts) })
+    }
+
+    // renders the diagnostic with the file it points to
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let file = diagnostic.span.as_ref().map_or(0, |span| span.file);
+        let source = &self.sources[file];
+        diagnostic.render(&source.path, source.text)
+    }
+
+    fn load_module(&mut self, path: &Path, name: Option<&'static str>, import: Option<&Span>) -> Result<(), Diagnostic> {
+        let read_err = |err: std::io::Error| match import {
+            Some(span) => semantic_err!(span, "Could not read module `{}`: {err}", path.display()),
+            None => Diagnostic::error(None, "IOError", format!("Could not read file `{}`: {err}", path.display())),
+        };
+
+        let canonical = path.canonicalize().map_err(read_err)?;
+        match self.modules.get(&canonical) {
+            Some(State::Loading) => {
+                return Err(semantic_err!(import.unwrap(), "Circular import of `{}`", path.display()));
+            },
+            Some(State::Loaded) => return Ok(()),
+            None => {},
+        }
+
+        let text = self.strings.intern(std::fs::read_to_string(path).map_err(read_err)?);
+        let file = self.sources.len();
+        self.sources.push(Source { path: path.display().to_string(), text });
+        self.modules.insert(canonical.clone(), State::Loading);
+
+        let mut lexer = Lexer::new(text.as_bytes(), file);
+        let ast = parser::parse(&mut lexer)?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut imported = HashSet::new();
+        let mut stmts = Vec::with_capacity(ast.stmts.len());
+        for stmt in ast.stmts {
+            let StmtKind::Import(import) = stmt.kind else {
+                stmts.push(stmt);
+                continue;
+            };
+
+            let import_path = dir.join(import);
+            let module = import_path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+            if module.is_empty() || !module.bytes().all(|c| c.is_ascii_alphanumeric()) || !module.as_bytes()[0].is_ascii_alphabetic() {
+                return Err(semantic_err!(stmt.span, "Module name `{module}` is not a valid identifier"));
+            }
+            let module = self.strings.intern(module.to_string());
+
+            let canonical_import = import_path.canonicalize().map_err(|err| {
+                semantic_err!(stmt.span, "Could not read module `{}`: {err}", import_path.display())
+            })?;
+            if let Some(other) = self.names.get(module) {
+                if *other != canonical_import {
+                    return Err(semantic_err!(stmt.span, "Module `{module}` is already imported from `{}`", other.display()));
+                }
+            }
+            self.names.insert(module, canonical_import);
+
+            self.load_module(&import_path, Some(module), Some(&stmt.span))?;
+            imported.insert(module);
+        }
+
+        let mut resolver = Resolver {
+            strings: &mut self.strings,
+            prefix: name,
+            items: HashSet::new(),
+            imported,
+            locals: Vec::new(),
+        };
+        for stmt in &stmts {
+            match &stmt.kind {
+                StmtKind::FnDecl(data) => { resolver.items.insert(data.name); },
+                StmtKind::VarDecl(name, _) | StmtKind::VarDeclAssign { name, .. } | StmtKind::ConstDecl { name, .. }
+                | StmtKind::StructDecl { name, .. } => {
+                    resolver.items.insert(name);
+                },
+                _ => {},
+            }
+        }
+
+        for mut stmt in stmts {
+            resolver.resolve_stmt(&mut stmt)?;
+
+            // the same extern function may be declared by several modules
+            if let StmtKind::ExternFnDecl { name, params, result } = &stmt.kind {
+                let signature = (params.clone(), *result);
+                if self.externs.get(name) == Some(&signature) {
+                    continue;
+                }
+                self.externs.insert(name, signature);
+            }
+            self.stmts.push(stmt);
+        }
+
+        self.modules.insert(canonical, State::Loaded);
+        Ok(())
+    }
+}
+
+struct Resolver<'s> {
+    strings: &'s mut Strings,
+    prefix: Option<&'static str>,
+    items: HashSet<&'static str>,
+    imported: HashSet<&'static str>,
+    locals: Vec<HashSet<&'static str>>,
+}
+
+impl Resolver<'_> {
+    fn qualify(&mut self, name: &'static str) -> &'static str {
+        match self.prefix {
+            Some(prefix) => self.strings.intern(format!("{prefix}::{name}")),
+            None => name,
+        }
+    }
+
+    fn resolve_name(&mut self, name: &'static str, is_var: bool, span: &Span) -> Result<&'static str, Diagnostic> {
+        if let Some((module, _)) = name.split_once("::") {
+            if !self.imported.contains(module) {
+                return Err(semantic_err!(span, "Module `{module}` is not imported"));
+            }
+            return Ok(name);
+        }
+
+        if is_var && self.locals.iter().any(|scope| scope.contains(name)) {
+            return Ok(name);
+        }
+
+        Ok(if self.items.contains(name) { self.qualify(name) } else { name })
+    }
+
+    // `pos.x`, only the variable is resolved
+    fn resolve_var_path(&mut self, path: &'static str, span: &Span) -> Result<&'static str, Diagnostic> {
+        match path.split_once('.') {
+            Some((name, fields)) => {
+                let name = self.resolve_name(name, true, span)?;
+                Ok(self.strings.intern(format!("{name}.{fields}")))
+            },
+            None => self.resolve_name(path, true, span),
+        }
+    }
+
+    fn resolve_type(&mut self, ty: &mut DataType, span: &Span) -> Result<(), Diagnostic> {
+        if let DataType::Struct(name) = ty {
+            *name = self.resolve_name(name, false, span)?;
+        }
+        Ok(())
+    }
+
+    fn declare(&mut self, name: &mut &'static str) {
+        match self.locals.last_mut() {
+            Some(scope) => { scope.insert(name); },
+            None => *name = self.qualify(name),
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &mut Expr) -> Result<(), Diagnostic> {
+        match &mut expr.kind {
+            ExprKind::Num(_) | ExprKind::Bool(_) | ExprKind::Str(_) => {},
+            ExprKind::Var(name) => *name = self.resolve_name(name, true, &expr.span)?,
+            ExprKind::BinOp(data) => {
+                self.resolve_expr(&mut data.lhs)?;
+                self.resolve_expr(&mut data.rhs)?;
+            },
+            ExprKind::UnaryOp(data) => self.resolve_expr(&mut data.expr)?,
+            ExprKind::Array(elems) => {
+                for elem in elems { self.resolve_expr(elem)?; }
+            },
+            ExprKind::Index(data) => {
+                data.name = self.resolve_name(data.name, true, &expr.span)?;
+                self.resolve_expr(&mut data.index)?;
+            },
+            ExprKind::Field(data) => data.name = self.resolve_name(data.name, true, &expr.span)?,
+            ExprKind::Struct(data) => {
+                data.name = self.resolve_name(data.name, false, &expr.span)?;
+                for (_, field) in &mut data.fields { self.resolve_expr(field)?; }
+            },
+            ExprKind::FnCall(data) => {
+                data.name = self.resolve_name(data.name, false, &expr.span)?;
+                for arg in &mut data.args { self.resolve_expr(arg)?; }
+            },
+        }
+        Ok(())
+    }
+
+    fn resolve_block(&mut self, block: &mut Block<'static>) -> Result<(), Diagnostic> {
+        self.locals.push(HashSet::new());
+        for stmt in block { self.resolve_stmt(stmt)?; }
+        self.locals.pop();
+        Ok(())
+    }
+
+    fn resolve_stmt(&mut self, stmt: &mut Stmt<'static>) -> Result<(), Diagnostic> {
+        let span = &stmt.span;
+        match &mut stmt.kind {
+            StmtKind::Import(_) => {
+                return Err(semantic_err!(span, "You cannot use that statement in the local scope"));
+            },
+
+            StmtKind::StructDecl { .. } if !self.locals.is_empty() => {
+                return Err(semantic_err!(span, "You cannot use that statement in the local scope"));
+            },
+
+            StmtKind::Return | StmtKind::Break | StmtKind::Continue | StmtKind::Yield => {},
+
+            StmtKind::ExternFnDecl { params, result, .. } => {
+                for ty in params.iter_mut().chain(result) { self.resolve_type(ty, span)?; }
+            },
+
+            StmtKind::StructDecl { name, fields } => {
+                for field in fields { self.resolve_type(&mut field.ty, span)?; }
+                self.declare(name);
+            },
+
+            StmtKind::FnDecl(data) => {
+                for ty in data.params.iter_mut().map(|p| &mut p.ty).chain(&mut data.result) {
+                    self.resolve_type(ty, span)?;
+                }
+                data.name = self.qualify(data.name);
+                self.locals.push(data.params.iter().map(|p| p.name).collect());
+                for stmt in &mut data.body { self.resolve_stmt(stmt)?; }
+                self.locals.pop();
+            },
+
+            StmtKind::VarDecl(name, ty) => {
+                self.resolve_type(ty, span)?;
+                self.declare(name);
+            },
+
+            StmtKind::VarDeclAssign { name, ty, expr } => {
+                if let Some(ty) = ty { self.resolve_type(ty, span)?; }
+                self.resolve_expr(expr)?;
+                self.declare(name);
+            },
+
+            StmtKind::ConstDecl { name, expr } => {
+                self.resolve_expr(expr)?;
+                self.declare(name);
+            },
+
+            StmtKind::VarAssign { name, expr } | StmtKind::CompoundAssign { name, expr, .. } => {
+                *name = self.resolve_name(name, true, span)?;
+                self.resolve_expr(expr)?;
+            },
+
+            StmtKind::FieldAssign { name, expr, .. } => {
+                *name = self.resolve_name(name, true, span)?;
+                self.resolve_expr(expr)?;
+            },
+
+            StmtKind::IndexAssign { name, index, expr } => {
+                *name = self.resolve_name(name, true, span)?;
+                self.resolve_expr(index)?;
+                self.resolve_expr(expr)?;
+            },
+
+            StmtKind::ReturnVal(expr) | StmtKind::Sleep(expr) | StmtKind::Print(expr) => self.resolve_expr(expr)?,
+
+            StmtKind::FnCall { name, args } => {
+                *name = self.resolve_name(name, false, span)?;
+                for arg in args { self.resolve_expr(arg)?; }
+            },
+
+            StmtKind::Log { args, .. } => {
+                for arg in args { self.resolve_expr(arg)?; }
+            },
+
+            StmtKind::BuilinFnCall { arg, .. } => {
+                let mut template = String::with_capacity(arg.len());
+                for part in cmd_parts(arg) {
+                    match part {
+                        CmdPart::Text(text) => template.push_str(text),
+                        CmdPart::Var(var) => template += &format!("{{{}}}", self.resolve_var_path(var, span)?),
+                    }
+                }
+                *arg = self.strings.intern(template);
+            },
+
+            StmtKind::If { cond, then, elzeifs, elze } => {
+                self.resolve_expr(cond)?;
+                self.resolve_block(then)?;
+                for elzeif in elzeifs {
+                    self.resolve_expr(&mut elzeif.cond)?;
+                    self.resolve_block(&mut elzeif.then)?;
+                }
+                self.resolve_block(elze)?;
+            },
+
+            StmtKind::For { body, init, cond, post } => {
+                self.locals.push(HashSet::new());
+                if let Some(init) = init { self.resolve_stmt(init)?; }
+                if let Some(cond) = cond { self.resolve_expr(cond)?; }
+                if let Some(post) = post { self.resolve_stmt(post)?; }
+                self.resolve_block(body)?;
+                self.locals.pop();
+            },
+
+            StmtKind::While { cond, body } => {
+                self.resolve_expr(cond)?;
+                self.resolve_block(body)?;
+            },
+
+            StmtKind::Loop(body) => self.resolve_block(body)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic::Analyzer;
+
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("luba-module-{test}-{}", std::process::id()));
+        for (path, text) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    fn fn_names(ast: &Ast<'static>) -> Vec<&'static str> {
+        ast.stmts.iter().filter_map(|stmt| match &stmt.kind {
+            StmtKind::FnDecl(data) => Some(data.name),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn link_modules() {
+        let dir = write_files("link", &[
+            ("main.luba", "
+                import \"lib/geom.luba\"
+                fn dist(a) int { return a }
+                fn main() {
+                    x := geom::dist(1, 2) + dist(3)
+                    geom::scale = x
+                }
+            "),
+            ("lib/geom.luba", "
+                scale := 2
+                fn sq(x) int { return x * x * scale }
+                fn dist(x, y) int {
+                    scale := 1
+                    return sq(x + y) + scale
+                }
+            "),
+        ]);
+
+        let mut loader = Loader::default();
+        let ast = loader.load(dir.join("main.luba").to_str().unwrap()).unwrap();
+        assert_eq!(fn_names(&ast), ["geom::sq", "geom::dist", "dist", "main"]);
+
+        let (_, diagnostics) = Analyzer::analyze(&ast);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+
+        // the local `scale` shadows the global one
+        let StmtKind::FnDecl(dist) = &ast.stmts[2].kind else { unreachable!() };
+        let StmtKind::ReturnVal(expr) = &dist.body[1].kind else { unreachable!() };
+        assert_eq!(expr.to_string(), "[+ geom::sq([+ x y]) scale]");
+    }
+
+    #[test]
+    fn import_errors() {
+        let dir = write_files("errors", &[
+            ("a.luba", "import \"b.luba\"\nfn main() {}"),
+            ("b.luba", "import \"a.luba\""),
+            ("c.luba", "fn main() {\n    geom::dist()\n}"),
+        ]);
+
+        let mut loader = Loader::default();
+        let err = loader.load(dir.join("a.luba").to_str().unwrap()).unwrap_err();
+        assert!(err.message.starts_with("Circular import of"), "{}", err.message);
+        assert_eq!(err.span.unwrap().file, 1);
+
+        let mut loader = Loader::default();
+        let err = loader.load(dir.join("c.luba").to_str().unwrap()).unwrap_err();
+        assert_eq!(err.message, "Module `geom` is not imported");
+        assert_eq!(err.span.unwrap().start.row, 2);
+    }
+}
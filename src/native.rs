@@ -0,0 +1,791 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::{compilation_err, datapack::{self, Files, Options}, exit_failure, lexer::{BinOpKind, UnOpKind}, emitter::log_color, parser::{cmd_parts, Ast, Block, CmdPart, DataType, ElseIf, Entry, Expr, ExprKind, LogLevel, Stmt, StmtKind}, semantic::{Name, Scope, ScopeIdx, Type}};
+
+// Native backend. Every luba function becomes `<ns>:fn/<name>` and every
+// nested block becomes `<ns>:fn/<name>/<idx>`. Variables are fake players of
+// the `<ns>` objective, so the whole program runs in a single tick.
+//
+// `break`, `continue` and `return` set the `$<fn>.ctl` score to one of the
+// `CTL_*` values and leave the current mcfunction. Callers of nested blocks
+// check that score and keep leaving until the loop or the function that
+// handles it.
+
+const GLOBAL: &str = "__global";
+
+const CTL_BREAK:    i32 = 1;
+const CTL_CONTINUE: i32 = 2;
+const CTL_RETURN:   i32 = 3;
+
+struct Compiler<'a> {
+    ns: &'a str,
+    log_level:  Option<LogLevel>,
+    log_target: &'a str,
+    externs: HashSet<&'a str>,
+    functions: Files,
+    lines: Vec<String>,
+
+    fn_name: String,
+    // luba functions called by each function, the frames are fixed fake
+    // players, so the call graph must not have cycles
+    calls: BTreeMap<&'a str, BTreeSet<&'a str>>,
+    caller: &'a str,
+    sub_count: usize,
+    cond_count: usize,
+
+    curr_scope_idx: ScopeIdx,
+    scopes: Vec<Scope<'a>>,
+}
+
+macro_rules! cmd {
+    ($comp:ident, $($arg:tt)*) => {
+        $comp.lines.push(format!($($arg)*));
+    };
+}
+
+impl<'a> Compiler<'a> {
+    fn new(ast: &'a Ast<'a>, semdata: Vec<Scope<'a>>, opts: &'a Options) -> Self {
+        let externs = ast.stmts.iter().filter_map(|stmt| match stmt.kind {
+            StmtKind::ExternFnDecl { name, .. } => Some(name),
+            _ => None,
+        }).collect();
+
+        Compiler {
+            ns: &opts.namespace,
+            log_level:  opts.log_level,
+            log_target: &opts.log_target,
+            externs,
+            functions: Files::new(),
+            lines: Vec::new(),
+            fn_name: GLOBAL.to_string(),
+            calls: BTreeMap::new(),
+            caller: GLOBAL,
+            sub_count: 0,
+            cond_count: 0,
+            curr_scope_idx: 0,
+            scopes: semdata,
+        }
+    }
+
+    // minecraft resource locations must be lowercase, identifiers can't contain `_`,
+    // functions of the module `geom` go to the `fn/geom/` directory
+    fn mangle(name: &str) -> String {
+        let mut mangled = String::with_capacity(name.len());
+        for c in name.replace("::", "/").chars() {
+            if c.is_ascii_uppercase() {
+                mangled.push('_');
+                mangled.push(c.to_ascii_lowercase());
+            } else {
+                mangled.push(c);
+            }
+        }
+        mangled
+    }
+
+    fn holder(&self, name: &str) -> String {
+        format!("${}.{name}", self.fn_name)
+    }
+
+    fn tmp(&self, depth: usize) -> String {
+        self.holder(&format!("t{depth}"))
+    }
+
+    fn ctl(&self) -> String {
+        self.holder("ctl")
+    }
+
+    fn var(&self, name: Name, scope: ScopeIdx) -> String {
+        if let Some(Type::Var(slot, _)) = self.scopes[scope].items.get(name) {
+            if scope == 0 {
+                format!("${GLOBAL}.{slot}")
+            } else {
+                self.holder(&slot.to_string())
+            }
+        } else if scope != 0 {
+            self.var(name, self.scopes[scope].parent)
+        } else {
+            unreachable!("{name}");
+        }
+    }
+
+    // compiles the function body produced by `f` into a new mcfunction
+    fn sub_function(&mut self, f: impl FnOnce(&mut Self, &str)) -> String {
+        let id = format!("fn/{}/{}", self.fn_name, self.sub_count);
+        self.sub_count += 1;
+
+        let outer = std::mem::take(&mut self.lines);
+        f(self, &id);
+        let lines = std::mem::replace(&mut self.lines, outer);
+        self.functions.insert(id.clone(), lines.join("\n"));
+        id
+    }
+
+    fn leave_unless_normal(&mut self) {
+        let ns = self.ns;
+        let ctl = self.ctl();
+        cmd!(self, "execute unless score {ctl} {ns} matches 0 run return 0");
+    }
+
+    // returns the score holder that contains the result
+    fn compile_expr(&mut self, expr: &Expr, scope: ScopeIdx, depth: usize) -> String {
+        let ns = self.ns;
+        match &expr.kind {
+            ExprKind::Var(name) => self.var(name, scope),
+
+            ExprKind::Array(_) | ExprKind::Index(_) => {
+                compilation_err!("{}: arrays are only supported by the redvm backend", expr.span);
+            },
+
+            ExprKind::Field(_) | ExprKind::Struct(_) => {
+                compilation_err!("{}: structs are only supported by the redvm backend", expr.span);
+            },
+
+            ExprKind::Str(_) => {
+                compilation_err!("{}: strings are only supported by the redvm backend", expr.span);
+            },
+
+            ExprKind::Num(n) => {
+                let dst = self.tmp(depth);
+                cmd!(self, "scoreboard players set {dst} {ns} {n}");
+                dst
+            },
+
+            ExprKind::Bool(b) => {
+                let dst = self.tmp(depth);
+                cmd!(self, "scoreboard players set {dst} {ns} {}", *b as i32);
+                dst
+            },
+
+            ExprKind::BinOp(data) if matches!(data.op, BinOpKind::And | BinOpKind::Or) => {
+                // the right operand is evaluated in a sub function only when it decides the result
+                let dst = self.tmp(depth);
+                let lhs = self.compile_expr(&data.lhs, scope, depth);
+                cmd!(self, "execute store result score {dst} {ns} if score {lhs} {ns} matches 1..");
+
+                let rhs_fn = self.sub_function(|comp, _| {
+                    let rhs = comp.compile_expr(&data.rhs, scope, depth+1);
+                    cmd!(comp, "execute store result score {dst} {ns} if score {rhs} {ns} matches 1..");
+                });
+                let lhs_result = if data.op == BinOpKind::And { 1 } else { 0 };
+                cmd!(self, "execute if score {dst} {ns} matches {lhs_result} run function {ns}:{rhs_fn}");
+                dst
+            },
+
+            ExprKind::BinOp(data) => {
+                let dst = self.tmp(depth);
+                let lhs = self.compile_expr(&data.lhs, scope, depth);
+                if lhs != dst {
+                    cmd!(self, "scoreboard players operation {dst} {ns} = {lhs} {ns}");
+                }
+
+                let rhs = self.compile_expr(&data.rhs, scope, depth+1);
+                let op = &data.op;
+                match op {
+                    BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul | BinOpKind::Div | BinOpKind::Mod => {
+                        cmd!(self, "scoreboard players operation {dst} {ns} {op}= {rhs} {ns}");
+                    },
+                    BinOpKind::Gt | BinOpKind::Ge | BinOpKind::Lt | BinOpKind::Le => {
+                        cmd!(self, "execute store result score {dst} {ns} if score {dst} {ns} {op} {rhs} {ns}");
+                    },
+                    BinOpKind::Eq => {
+                        cmd!(self, "execute store result score {dst} {ns} if score {dst} {ns} = {rhs} {ns}");
+                    },
+                    BinOpKind::Ne => {
+                        cmd!(self, "execute store result score {dst} {ns} unless score {dst} {ns} = {rhs} {ns}");
+                    },
+                    BinOpKind::And | BinOpKind::Or => unreachable!(),
+                }
+                dst
+            },
+
+            ExprKind::UnaryOp(data) => {
+                let dst = self.tmp(depth);
+                let val = self.compile_expr(&data.expr, scope, depth);
+                match data.op {
+                    UnOpKind::Not => {
+                        cmd!(self, "execute store result score {dst} {ns} if score {val} {ns} matches ..0");
+                    },
+                    // `~x` is `-x - 1` in two's complement
+                    UnOpKind::Neg | UnOpKind::BitNot => {
+                        let minus_one = self.tmp(depth+1);
+                        cmd!(self, "scoreboard players set {minus_one} {ns} -1");
+                        if val != dst {
+                            cmd!(self, "scoreboard players operation {dst} {ns} = {val} {ns}");
+                        }
+                        cmd!(self, "scoreboard players operation {dst} {ns} *= {minus_one} {ns}");
+                        if data.op == UnOpKind::BitNot {
+                            cmd!(self, "scoreboard players remove {dst} {ns} 1");
+                        }
+                    },
+                }
+                dst
+            },
+
+            ExprKind::FnCall(data) => {
+                self.compile_call(data.name, &data.args, scope, depth);
+                let dst = self.tmp(depth);
+                cmd!(self, "scoreboard players operation {dst} {ns} = ${}.0 {ns}", Self::mangle(data.name));
+                dst
+            },
+        }
+    }
+
+    fn compile_call(&mut self, name: &'a str, args: &[Expr], scope: ScopeIdx, depth: usize) {
+        let ns = self.ns;
+        // every argument is evaluated before any parameter of the callee is set
+        for (i, arg) in args.iter().enumerate() {
+            let dst = self.tmp(depth+i);
+            let val = self.compile_expr(arg, scope, depth+i);
+            if val != dst {
+                cmd!(self, "scoreboard players operation {dst} {ns} = {val} {ns}");
+            }
+        }
+
+        let has_result = match self.scopes[0].items.get(name) {
+            Some(Type::FnDecl(info)) => info.result.is_some(),
+            _ => unreachable!("{name}")
+        };
+
+        let callee = Self::mangle(name);
+        if !self.externs.contains(name) {
+            self.calls.entry(self.caller).or_default().insert(name);
+        }
+        if self.externs.contains(name) {
+            for i in 0..args.len() {
+                let val = self.tmp(depth+i);
+                cmd!(self, "execute store result storage {ns} eargs.{i} int 1 run scoreboard players get {val} {ns}");
+            }
+
+            // the returned value is read from the result holder like for luba functions
+            if has_result {
+                cmd!(self, "execute store result score ${callee}.0 {ns} run function {ns}:extern/{name} with storage {ns} eargs");
+            } else {
+                cmd!(self, "function {ns}:extern/{name} with storage {ns} eargs");
+            }
+            return;
+        }
+
+        for i in 0..args.len() {
+            let val = self.tmp(depth+i);
+            cmd!(self, "scoreboard players operation ${callee}.{} {ns} = {val} {ns}", i + has_result as usize);
+        }
+        cmd!(self, "function {ns}:fn/{callee}");
+    }
+
+    fn compile_if(&mut self, cond: &Expr, then: &Block<'a>, elzeifs: &[ElseIf<'a>], elze: &Block<'a>, scope: ScopeIdx) {
+        let ns = self.ns;
+        // the condition is kept in its own holder because the `then` block
+        // may overwrite temporaries before the `else` block is selected
+        let flag = self.holder(&format!("c{}", self.cond_count));
+        self.cond_count += 1;
+        let val = self.compile_expr(cond, scope, 0);
+        cmd!(self, "scoreboard players operation {flag} {ns} = {val} {ns}");
+
+        let then_fn = self.sub_function(|comp, _| comp.compile_block(then));
+        cmd!(self, "execute if score {flag} {ns} matches 1.. run function {ns}:{then_fn}");
+
+        if let Some((elzeif, rest)) = elzeifs.split_first() {
+            let else_fn = self.sub_function(|comp, _| {
+                comp.compile_if(&elzeif.cond, &elzeif.then, rest, elze, scope);
+            });
+            cmd!(self, "execute unless score {flag} {ns} matches 1.. run function {ns}:{else_fn}");
+        } else if !elze.is_empty() {
+            let else_fn = self.sub_function(|comp, _| comp.compile_block(elze));
+            cmd!(self, "execute unless score {flag} {ns} matches 1.. run function {ns}:{else_fn}");
+        } else {
+            self.curr_scope_idx += 1; // the empty `else` block still has a scope
+        }
+
+        self.leave_unless_normal();
+    }
+
+    // every iteration is a recursive call of the loop function, the number
+    // of iterations isn't known here, so a long loop is only limited by
+    // `maxCommandChainLength` at run time (see the README)
+    fn compile_loop(&mut self, cond: Option<&Expr>, body: &Block<'a>, post: Option<&Stmt<'a>>, scope: ScopeIdx) {
+        let ns = self.ns;
+        let ctl = self.ctl();
+
+        let loop_fn = self.sub_function(|comp, id| {
+            if let Some(e) = cond {
+                let val = comp.compile_expr(e, scope, 0);
+                cmd!(comp, "execute unless score {val} {ns} matches 1.. run return 0");
+            }
+
+            let body_fn = comp.sub_function(|comp, _| comp.compile_block(body));
+            cmd!(comp, "function {ns}:{body_fn}");
+            cmd!(comp, "execute if score {ctl} {ns} matches {CTL_CONTINUE} run scoreboard players set {ctl} {ns} 0");
+            comp.leave_unless_normal();
+
+            if let Some(s) = post {
+                comp.compile_stmt(s, scope);
+            }
+
+            cmd!(comp, "function {ns}:{id}");
+        });
+
+        cmd!(self, "function {ns}:{loop_fn}");
+        cmd!(self, "execute if score {ctl} {ns} matches {CTL_BREAK} run scoreboard players set {ctl} {ns} 0");
+        self.leave_unless_normal();
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt<'a>, scope: ScopeIdx) {
+        let ns = self.ns;
+        match &stmt.kind {
+            StmtKind::VarDecl(_, DataType::Array(..)) | StmtKind::IndexAssign { .. } => {
+                compilation_err!("{}: arrays are only supported by the redvm backend", stmt.span);
+            },
+
+            // struct values take several scores, which this backend doesn't lay out
+            StmtKind::StructDecl { .. } | StmtKind::FieldAssign { .. } => {
+                compilation_err!("{}: structs are only supported by the redvm backend", stmt.span);
+            },
+
+            StmtKind::VarDecl(_, DataType::Str) | StmtKind::Print(_) => {
+                compilation_err!("{}: strings are only supported by the redvm backend", stmt.span);
+            },
+
+            StmtKind::VarDecl(..) | StmtKind::ConstDecl { .. } => {},
+
+            StmtKind::VarAssign { name, expr } | StmtKind::VarDeclAssign { name, expr, .. } => {
+                let dst = self.var(name, scope);
+                let val = self.compile_expr(expr, scope, 0);
+                cmd!(self, "scoreboard players operation {dst} {ns} = {val} {ns}");
+            },
+
+            StmtKind::CompoundAssign { name, op, expr } => {
+                let dst = self.var(name, scope);
+                match (op, &expr.kind) {
+                    (BinOpKind::Add, ExprKind::Num(n)) => { cmd!(self, "scoreboard players add {dst} {ns} {n}"); },
+                    (BinOpKind::Sub, ExprKind::Num(n)) => { cmd!(self, "scoreboard players remove {dst} {ns} {n}"); },
+                    _ => {
+                        let val = self.compile_expr(expr, scope, 0);
+                        cmd!(self, "scoreboard players operation {dst} {ns} {op}= {val} {ns}");
+                    },
+                }
+            },
+
+            StmtKind::ReturnVal(expr) => {
+                let dst = self.holder("0");
+                let val = self.compile_expr(expr, scope, 0);
+                cmd!(self, "scoreboard players operation {dst} {ns} = {val} {ns}");
+                cmd!(self, "scoreboard players set {} {ns} {CTL_RETURN}", self.ctl());
+                cmd!(self, "return 0");
+            },
+
+            StmtKind::Return => {
+                cmd!(self, "scoreboard players set {} {ns} {CTL_RETURN}", self.ctl());
+                cmd!(self, "return 0");
+            },
+
+            StmtKind::Break => {
+                cmd!(self, "scoreboard players set {} {ns} {CTL_BREAK}", self.ctl());
+                cmd!(self, "return 0");
+            },
+
+            StmtKind::Continue => {
+                cmd!(self, "scoreboard players set {} {ns} {CTL_CONTINUE}", self.ctl());
+                cmd!(self, "return 0");
+            },
+
+            StmtKind::Yield | StmtKind::Sleep(_) => {
+                compilation_err!("{}: `yield` and `sleep` are only supported by the redvm backend", stmt.span);
+            },
+
+            StmtKind::FnCall { name, args } => {
+                self.compile_call(name, args, scope, 0);
+            },
+
+            // the values are evaluated first and joined into one json text
+            StmtKind::Log { level, format, args } => {
+                if self.log_level.is_none_or(|min| *level < min) { return; }
+                for (i, arg) in args.iter().enumerate() {
+                    let dst = self.tmp(i);
+                    let val = self.compile_expr(arg, scope, i);
+                    if val != dst {
+                        cmd!(self, "scoreboard players operation {dst} {ns} = {val} {ns}");
+                    }
+                }
+
+                let mut text = format!("{{\"text\":\"[{level}] \",\"color\":\"{}\"}}", log_color(level));
+                for (i, part) in format.split("{}").enumerate() {
+                    if i > 0 {
+                        text += &format!(",{{\"score\":{{\"name\":\"{}\",\"objective\":\"{ns}\"}}}}", self.tmp(i-1));
+                    }
+                    if !part.is_empty() {
+                        text += &format!(",\"{}\"", json_escape(part));
+                    }
+                }
+                cmd!(self, "tellraw {} [{text}]", self.log_target);
+            },
+
+            StmtKind::BuilinFnCall { name, arg } => {
+                match *name {
+                    "cmd" => {
+                        let parts = cmd_parts(arg);
+                        if !parts.iter().any(|part| matches!(part, CmdPart::Var(_))) {
+                            cmd!(self, "{arg}");
+                            return;
+                        }
+
+                        // variables are passed to the macro function as `$(0)`, `$(1)`, ...
+                        let mut body = String::from("$");
+                        let mut arg_count = 0;
+                        for part in parts {
+                            match part {
+                                CmdPart::Text(text) => body.push_str(text),
+                                CmdPart::Var(var) => {
+                                    let holder = self.var(var, scope);
+                                    cmd!(self, "execute store result storage {ns} eargs.{arg_count} int 1 run scoreboard players get {holder} {ns}");
+                                    body += &format!("$({arg_count})");
+                                    arg_count += 1;
+                                },
+                            }
+                        }
+                        let macro_fn = self.sub_function(|comp, _| comp.lines.push(body));
+                        cmd!(self, "function {ns}:{macro_fn} with storage {ns} eargs");
+                    },
+
+                    _ => unreachable!()
+                }
+            },
+
+            StmtKind::If { cond, then, elzeifs, elze } => {
+                self.compile_if(cond, then, elzeifs, elze, scope);
+            },
+
+            StmtKind::For { body, init, cond, post } => {
+                let forloop_scope = self.curr_scope_idx;
+
+                if let Some(s) = init {
+                    self.compile_stmt(s, forloop_scope);
+                }
+
+                self.compile_loop(cond.as_ref(), body, post.as_deref(), forloop_scope);
+            },
+
+            StmtKind::While { cond, body } => self.compile_loop(Some(cond), body, None, scope),
+            StmtKind::Loop(body) => self.compile_loop(None, body, None, scope),
+
+            StmtKind::ExternFnDecl { .. } | StmtKind::Import(_) => {},
+
+            StmtKind::FnDecl(data) => {
+                let outer = std::mem::take(&mut self.lines);
+                self.fn_name = Self::mangle(data.name);
+                self.caller = data.name;
+                self.sub_count = 0;
+                self.cond_count = 0;
+
+                cmd!(self, "scoreboard players set {} {ns} 0", self.ctl());
+                self.compile_block(&data.body);
+
+                let lines = std::mem::replace(&mut self.lines, outer);
+                self.functions.insert(format!("fn/{}", self.fn_name), lines.join("\n"));
+                self.fn_name = GLOBAL.to_string();
+                self.caller = GLOBAL;
+            },
+        }
+    }
+
+    fn recursion_err(&self) -> Option<String> {
+        let name = find_recursion(&self.calls)?;
+        let Some(Type::FnDecl(info)) = self.scopes[0].items.get(name) else { unreachable!() };
+        Some(format!("{}: recursive function `{name}` is only supported by the redvm backend", info.span))
+    }
+
+    fn compile_block(&mut self, block: &Block<'a>) {
+        let curr_scope = self.curr_scope_idx;
+        self.curr_scope_idx += 1;
+        for stmt in block { self.compile_stmt(stmt, curr_scope); }
+    }
+}
+
+// the first function that can reach itself through `calls`
+fn find_recursion<'n>(calls: &BTreeMap<&'n str, BTreeSet<&'n str>>) -> Option<&'n str> {
+    calls.keys().copied().find(|&start| {
+        let mut stack: Vec<&str> = calls[start].iter().copied().collect();
+        let mut seen = BTreeSet::new();
+        while let Some(name) = stack.pop() {
+            if name == start { return true; }
+            if seen.insert(name) {
+                stack.extend(calls.get(name).into_iter().flatten());
+            }
+        }
+        false
+    })
+}
+
+fn json_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn compile<'a>(ast: &'a Ast<'a>, semdata: Vec<Scope<'a>>, opts: &'a Options) -> Files {
+    let ns = opts.namespace.as_str();
+    let mut comp = Compiler::new(ast, semdata, opts);
+
+    let entries = |entry: Entry| -> Vec<String> {
+        ast.stmts.iter().filter_map(|s| match &s.kind {
+            StmtKind::FnDecl(data) if data.entry == Some(entry) => Some(format!("{ns}:fn/{}", Compiler::mangle(data.name))),
+            _ => None,
+        }).collect()
+    };
+    let (load, tick) = (entries(Entry::Load), entries(Entry::Tick));
+
+    // global variables are initialized by the entry function before the
+    // `#[load]` functions and `main`
+    cmd!(comp, "scoreboard objectives add {ns} dummy");
+    comp.compile_block(&ast.stmts);
+    if let Some(err) = comp.recursion_err() {
+        compilation_err!("{err}");
+    }
+    for function in &load {
+        cmd!(comp, "function {function}");
+    }
+    if matches!(comp.scopes[0].items.get("main"), Some(Type::FnDecl(_))) {
+        cmd!(comp, "function {ns}:fn/main");
+    }
+
+    // `#[tick]` functions are put into the tag as is, the program is
+    // started on load so the globals are initialized before the first tick
+    let mut files = Files::new();
+    if !load.is_empty() || !tick.is_empty() {
+        datapack::entry_tags(&mut files, &[format!("{ns}:run")], &tick);
+    }
+    files.insert("pack.mcmeta".to_string(), datapack::mcmeta(opts, "luba program"));
+    files.insert(format!("data/{ns}/function/run.mcfunction"), comp.lines.join("\n") + "\n");
+    for (name, body) in comp.functions {
+        files.insert(format!("data/{ns}/function/{name}.mcfunction"), body + "\n");
+    }
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fold, lexer::Lexer, module::Strings, parser, semantic};
+
+    fn compile_src(src: &'static str) -> Files {
+        let mut strings = Strings::default();
+        let mut lexer = Lexer::new(src.as_bytes(), 0);
+        let mut ast = parser::parse(&mut lexer).unwrap();
+        let folded = fold::fold(&mut ast, &mut strings);
+        assert!(folded.is_empty(), "{folded:?}");
+        let (scopes, diagnostics) = semantic::Analyzer::analyze(&ast);
+        assert!(!diagnostics.iter().any(|d| d.is_error()), "{diagnostics:?}");
+        compile(&ast, scopes, &Options::default())
+    }
+
+    // the recursion error `compile` would report
+    fn recursion_err(src: &'static str) -> Option<String> {
+        let ast = parser::parse(&mut Lexer::new(src.as_bytes(), 0)).unwrap();
+        let (scopes, _) = semantic::Analyzer::analyze(&ast);
+        let opts = Options::default();
+        let mut comp = Compiler::new(&ast, scopes, &opts);
+        comp.compile_block(&ast.stmts);
+        comp.recursion_err()
+    }
+
+    fn function<'f>(files: &'f Files, name: &str) -> Vec<&'f str> {
+        files[&format!("data/luba/function/{name}.mcfunction")].lines().collect()
+    }
+
+    #[test]
+    fn function_per_decl() {
+        let files = compile_src("
+            fn addTwo(a, b) int {
+                return a + b
+            }
+
+            fn main() {
+                x := addTwo(1, 2)
+            }
+        ");
+
+        assert_eq!(function(&files, "run").last(), Some(&"function luba:fn/main"));
+        assert_eq!(function(&files, "fn/add_two"), [
+            "scoreboard players set $add_two.ctl luba 0",
+            "scoreboard players operation $add_two.t0 luba = $add_two.1 luba",
+            "scoreboard players operation $add_two.t0 luba += $add_two.2 luba",
+            "scoreboard players operation $add_two.0 luba = $add_two.t0 luba",
+            "scoreboard players set $add_two.ctl luba 3",
+            "return 0",
+        ]);
+        assert!(function(&files, "fn/main").contains(&"function luba:fn/add_two"));
+        assert_eq!(Compiler::mangle("geom::addTwo"), "geom/add_two");
+    }
+
+    #[test]
+    fn externs() {
+        let files = compile_src("
+            extern setblock(x: int, y: int, z: int)
+            extern getblock(x: int, y: int, z: int) -> bool
+
+            fn main() {
+                setblock(1, 2, 3)
+                b := getblock(1, 2, 3)
+            }
+        ");
+
+        let main = function(&files, "fn/main");
+        assert!(main.contains(&"function luba:extern/setblock with storage luba eargs"), "{main:?}");
+        assert!(main.contains(&"execute store result score $getblock.0 luba run function luba:extern/getblock with storage luba eargs"), "{main:?}");
+    }
+
+    #[test]
+    fn loops_and_branches() {
+        let files = compile_src("
+            fn main() {
+                for i := 0; i < 10; i = i+1 {
+                    if i == 5 { break } else { continue }
+                }
+            }
+        ");
+
+        let main = function(&files, "fn/main");
+        assert!(main.contains(&"function luba:fn/main/0"));
+        assert!(main.contains(&"execute if score $main.ctl luba matches 1 run scoreboard players set $main.ctl luba 0"));
+
+        let lup = function(&files, "fn/main/0");
+        assert_eq!(lup[..3], [
+            "scoreboard players operation $main.t0 luba = $main.2 luba",
+            "scoreboard players set $main.t1 luba 10",
+            "execute store result score $main.t0 luba if score $main.t0 luba < $main.t1 luba",
+        ]);
+        assert_eq!(lup.last(), Some(&"function luba:fn/main/0"));
+
+        let body = function(&files, "fn/main/1");
+        assert!(body.contains(&"execute if score $main.c0 luba matches 1.. run function luba:fn/main/2"));
+        assert!(body.contains(&"execute unless score $main.c0 luba matches 1.. run function luba:fn/main/3"));
+    }
+
+    #[test]
+    fn short_circuit() {
+        let files = compile_src("
+            fn main() {
+                a := true
+                z := 0
+                b := a || 1 / z > 0
+            }
+        ");
+
+        assert!(function(&files, "fn/main").contains(&"execute if score $main.t0 luba matches 0 run function luba:fn/main/0"));
+        assert_eq!(function(&files, "fn/main/0"), [
+            "scoreboard players set $main.t1 luba 1",
+            "scoreboard players operation $main.t1 luba /= $main.3 luba",
+            "scoreboard players set $main.t2 luba 0",
+            "execute store result score $main.t1 luba if score $main.t1 luba > $main.t2 luba",
+            "execute store result score $main.t0 luba if score $main.t1 luba matches 1..",
+        ]);
+    }
+
+    #[test]
+    fn infinite_loop() {
+        let files = compile_src("
+            fn main() {
+                loop { break }
+            }
+        ");
+
+        assert_eq!(function(&files, "fn/main/0"), [
+            "function luba:fn/main/1",
+            "execute if score $main.ctl luba matches 2 run scoreboard players set $main.ctl luba 0",
+            "execute unless score $main.ctl luba matches 0 run return 0",
+            "function luba:fn/main/0",
+        ]);
+    }
+
+    #[test]
+    fn cmd_interpolation() {
+        let files = compile_src("
+            fn main() {
+                x := 1
+                @cmd \"setblock {x} 64 {x} stone\"
+            }
+        ");
+
+        assert_eq!(function(&files, "fn/main")[3..], [
+            "execute store result storage luba eargs.0 int 1 run scoreboard players get $main.2 luba",
+            "execute store result storage luba eargs.1 int 1 run scoreboard players get $main.2 luba",
+            "function luba:fn/main/0 with storage luba eargs",
+        ]);
+        assert_eq!(function(&files, "fn/main/0"), ["$setblock $(0) 64 $(1) stone"]);
+    }
+
+    #[test]
+    fn entry_functions() {
+        let files = compile_src("
+            counter := 5
+
+            #[load] fn init() { counter = 0 }
+            #[tick] fn countUp() { counter += 1 }
+        ");
+
+        assert_eq!(function(&files, "run").last(), Some(&"function luba:fn/init"));
+        assert_eq!(files["data/minecraft/tags/function/load.json"], datapack::function_tag(&["luba:run".to_string()]));
+        assert_eq!(files["data/minecraft/tags/function/tick.json"], datapack::function_tag(&["luba:fn/count_up".to_string()]));
+        assert!(!compile_src("fn main() {}").contains_key("data/minecraft/tags/function/load.json"));
+    }
+
+    #[test]
+    fn recursion() {
+        let mut calls = BTreeMap::new();
+        calls.insert(GLOBAL, BTreeSet::from(["main"]));
+        calls.insert("main", BTreeSet::from(["a", "fact"]));
+        calls.insert("a", BTreeSet::from(["b"]));
+        calls.insert("b", BTreeSet::from(["c"]));
+        assert_eq!(find_recursion(&calls), None);
+
+        calls.insert("c", BTreeSet::from(["a"]));
+        assert_eq!(find_recursion(&calls), Some("a"));
+        calls.remove("c");
+        calls.insert("fact", BTreeSet::from(["fact"]));
+        assert_eq!(find_recursion(&calls), Some("fact"));
+
+        let files = compile_src("
+            x := twice(1)
+            fn main() { y := twice(x) + twice(2) }
+            fn twice(n: int) -> int { return n * 2 }
+        ");
+        assert!(function(&files, "fn/main").contains(&"function luba:fn/twice"));
+
+        // initializers after a function are called from the global scope
+        assert_eq!(recursion_err("
+            fn twice(n: int) -> int { return n * 2 }
+            x := twice(1)
+            fn main() { @log \"{}\" x }
+        "), None);
+        assert_eq!(recursion_err("
+            fn main() { @log \"{}\" fact(5) }
+            fn fact(n: int) -> int {
+                if n < 2 { return 1 }
+                return n * fact(n - 1)
+            }
+        ").unwrap(), "3:13: recursive function `fact` is only supported by the redvm backend");
+    }
+
+    #[test]
+    fn log_statements() {
+        let files = compile_src("
+            fn main() {
+                x := 1
+                @debug \"hidden\"
+                @warn \"x={} next={}\" x, x+1
+            }
+        ");
+        let opts = Options { log_level: Some(LogLevel::Info), log_target: "@a[tag=dev]".to_string(), ..Options::default() };
+        let mut lexer = Lexer::new(b"fn main() {\n@debug \"hidden\"\n@error \"shown\"\n}", 0);
+        let ast = parser::parse(&mut lexer).unwrap();
+        let release = compile(&ast, semantic::Analyzer::analyze(&ast).0, &opts);
+
+        assert_eq!(function(&files, "fn/main").last(), Some(&concat!(
+            "tellraw @a [{\"text\":\"[warn] \",\"color\":\"yellow\"},\"x=\",{\"score\":{\"name\":\"$main.t0\",\"objective\":\"luba\"}},",
+            "\" next=\",{\"score\":{\"name\":\"$main.t1\",\"objective\":\"luba\"}}]",
+        )));
+        assert!(function(&files, "fn/main").iter().any(|l| l.contains("[debug] ")));
+        assert_eq!(json_escape(r#"say "hi" \o/"#), r#"say \"hi\" \\o/"#);
+        assert_eq!(function(&release, "fn/main").iter().filter(|l| l.starts_with("tellraw")).collect::<Vec<_>>(), [
+            &"tellraw @a[tag=dev] [{\"text\":\"[error] \",\"color\":\"red\"},\"shown\"]",
+        ]);
+    }
+}
@@ -1,7 +1,6 @@
 use std::fmt;
 
-use crate::{lexer::*, semantic_err};
-use super::{syntax_err, unexpected_token_err, exit_failure};
+use crate::{diagnostic::Diagnostic, lexer::*, semantic_err, syntax_err, unexpected_token_err};
 
 pub type Block<'a> = Vec<Stmt<'a>>;
 
@@ -14,33 +13,100 @@ pub struct Ast<'a> {
 
 #[derive(Debug)]
 pub struct Stmt<'a> {
-    pub loc: Loc,
+    pub span: Span,
     pub kind: StmtKind<'a>
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DataType {
+    Int,
+    Bool,
+    // text in the NBT storage
+    Str,
+    // `[int; 16]`, elements are scalars
+    Array(&'static DataType, usize),
+    // the name of the `struct`, the layout is computed by the analyzer
+    Struct(&'static str),
+}
+
+// levels of `@debug`, `@log` (`info`), `@warn` and `@error`, statements
+// below `--log-level` are left out of the build
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "debug" => Some(LogLevel::Debug),
+            "info"  => Some(LogLevel::Info),
+            "warn"  => Some(LogLevel::Warn),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Param<'a> {
+    pub name: &'a str,
+    pub ty:   DataType,
+}
+
+// `#[load]` and `#[tick]` functions are called from the function tags of
+// minecraft, see `datapack::entry_tags`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Entry {
+    Load,
+    Tick,
+}
+
 #[derive(Debug)]
 pub struct FnDecl<'a> {
     pub name: &'a str,
-    pub params: Vec<&'a str>,
-    pub has_result: bool,
+    pub params: Vec<Param<'a>>,
+    pub result: Option<DataType>,
     pub body: Block<'a>,
+    pub entry: Option<Entry>,
 }
 
 #[derive(Debug)]
 pub enum StmtKind<'a> {
     FnDecl(FnDecl<'a>),
-    ExternFnDecl { name: &'a str, param_count: usize },
+    // names of extern parameters are optional and only document the signature
+    ExternFnDecl { name: &'a str, params: Vec<DataType>, result: Option<DataType> },
+    Import(&'a str),
     FnCall { name: &'a str, args: Vec<Expr> },
     VarAssign { name: &'a str, expr: Expr },
-    VarDeclAssign { name: &'a str, expr: Expr },
-    VarDecl(&'a str),
+    // `x += expr`, `x++` is `x += 1`
+    CompoundAssign { name: &'a str, op: BinOpKind, expr: Expr },
+    IndexAssign { name: &'a str, index: Expr, expr: Expr },
+    // `pos.x = expr`
+    FieldAssign { name: &'a str, fields: Vec<&'a str>, expr: Expr },
+    VarDeclAssign { name: &'a str, ty: Option<DataType>, expr: Expr },
+    VarDecl(&'a str, DataType),
+    // the value is replaced by a literal by `fold::fold`
+    ConstDecl { name: &'a str, expr: Expr },
+    StructDecl { name: &'a str, fields: Vec<Param<'a>> },
     ReturnVal(Expr),
     Return,
     BuilinFnCall { name: &'a str, arg: &'a str },
+    // `@log "x={} y={}" x, y+1`, every `{}` is replaced by the next value
+    Log { level: LogLevel, format: &'a str, args: Vec<Expr> },
     If { cond: Expr, then: Block<'a>, elzeifs: Vec<ElseIf<'a>>, elze: Block<'a>},
     For { body: Block<'a>, init: Option<Box<Stmt<'a>>>, cond: Option<Expr>, post: Option<Box<Stmt<'a>>> },
+    While { cond: Expr, body: Block<'a> },
+    Loop(Block<'a>),
     Continue,
     Break,
+    Yield,
+    Sleep(Expr),
+    // `@print expr`, the text is shown to every player with `tellraw`
+    Print(Expr),
 }
 
 #[derive(Debug)]
@@ -49,325 +115,649 @@ pub struct ElseIf<'a> {
     pub then: Block<'a>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Expr {
-    pub loc: Loc,
+    pub span: Span,
     pub kind: ExprKind
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ExprKind {
     FnCall(Box<FnCallExpr>),
     BinOp(Box<BinOpExpr>),
+    UnaryOp(Box<UnaryOpExpr>),
     Var(&'static str),
     Num(i32),
+    Bool(bool),
+    Str(&'static str),
+    // `[1, 2, 3]`
+    Array(Vec<Expr>),
+    Index(Box<IndexExpr>),
+    // `pos.x`, `line.start.y`
+    Field(Box<FieldExpr>),
+    // `Pos { x: 1, y: 2, z: 3 }`
+    Struct(Box<StructExpr>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct FnCallExpr {
     pub name: &'static str,
     pub args: Vec<Expr>
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct BinOpExpr {
     pub lhs: Expr,
     pub rhs: Expr,
     pub op: BinOpKind
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct IndexExpr {
+    pub name: &'static str,
+    pub index: Expr,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldExpr {
+    pub name: &'static str,
+    pub fields: Vec<&'static str>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct StructExpr {
+    pub name: &'static str,
+    pub fields: Vec<(&'static str, Expr)>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct UnaryOpExpr {
+    pub expr: Expr,
+    pub op: UnOpKind
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CmdPart<'a> {
+    Text(&'a str),
+    Var(&'a str),
+}
+
+fn is_ident(name: &str) -> bool {
+    name.bytes().next().is_some_and(|c| c.is_ascii_alphabetic()) &&
+    name.bytes().all(|c| c.is_ascii_alphanumeric())
+}
+
+// `name`, `module::name` or `name.field`
+fn is_path(name: &str) -> bool {
+    let mut fields = name.split('.');
+    fields.next().is_some_and(|path| path.split("::").all(is_ident)) && fields.all(is_ident)
+}
+
+// splits `@cmd "setblock {x} {y} {z} stone"` into text and variables,
+// braces that don't contain a name (nbt, json text) are the part of the text
+pub fn cmd_parts(template: &str) -> Vec<CmdPart<'_>> {
+    let mut parts = Vec::new();
+    let (mut text_start, mut pos) = (0, 0);
+    while let Some(open) = template[pos..].find('{').map(|i| pos+i) {
+        pos = open+1;
+        let Some(close) = template[pos..].find('}').map(|i| pos+i) else { break; };
+        let name = &template[pos..close];
+        if !is_path(name) { continue; }
+
+        if text_start < open {
+            parts.push(CmdPart::Text(&template[text_start..open]));
+        }
+        parts.push(CmdPart::Var(name));
+        (text_start, pos) = (close+1, close+1);
+    }
+
+    if text_start < template.len() {
+        parts.push(CmdPart::Text(&template[text_start..]));
+    }
+    parts
+}
 
-pub fn parse<'a>(lex: &mut Lexer<'a>) -> Ast<'a> {
+pub fn parse<'a>(lex: &mut Lexer<'a>) -> Result<Ast<'a>, Diagnostic> {
     let mut ast = Ast {
         stmts: Vec::new(),
     };
 
-    while let Some(_) = lex.peek_any() {
-        let stmt = parse_stmt(lex);
+    while lex.peek_any()?.is_some() {
+        let stmt = parse_stmt(lex)?;
         match stmt.kind {
             StmtKind::ExternFnDecl { .. } => {},
+            StmtKind::Import(_)  => {},
             StmtKind::FnDecl(_)  => {},
-            StmtKind::VarDecl(_) => {},
+            StmtKind::VarDecl(..) => {},
             StmtKind::VarDeclAssign { .. } => {},
+            StmtKind::ConstDecl { .. } => {},
+            StmtKind::StructDecl { .. } => {},
             _ => {
-                semantic_err!(stmt.loc, "You cannot use that statement in the global scope");
+                return Err(semantic_err!(stmt.span, "You cannot use that statement in the global scope"));
             }
         }
 
         ast.stmts.push(stmt);
     }
 
-    ast
+    Ok(ast)
 }
 
-fn parse_stmt<'a>(lex: &mut Lexer<'a>) -> Stmt<'a> {
-    let loc = lex.loc.clone();
-    match lex.expect_peek_any() {
+fn parse_stmt<'a>(lex: &mut Lexer<'a>) -> Result<Stmt<'a>, Diagnostic> {
+    lex.expect_peek_any()?;
+    let start = lex.loc.clone();
+    let kind = parse_stmt_kind(lex)?;
+    Ok(Stmt {
+        span: lex.span_from(start),
+        kind
+    })
+}
+
+fn parse_stmt_kind<'a>(lex: &mut Lexer<'a>) -> Result<StmtKind<'a>, Diagnostic> {
+    Ok(match lex.expect_peek_any()? {
         Token::Keyword(Keyword::Extern) => {
-            lex.next_any();
+            lex.next_any()?;
 
-            let name = lex.expect_ident();
+            let name = lex.expect_ident()?;
 
-            // parameters
-            lex.expect_punct(Punct::OpenParen);
-            let mut param_count = 0;
-            match lex.expect_any() {
-                Token::Punct(Punct::CloseParen) => {},
-                Token::Keyword(Keyword::Int) => {
-                    param_count += 1;
-                    loop {
-                        match lex.expect_any() {
-                            Token::Punct(Punct::CloseParen) => break,
-                            Token::Punct(Punct::Comma) => {
-                                match lex.expect_any() {
-                                    Token::Keyword(Keyword::Int) => param_count += 1,
-                                    t @ _ => { unexpected_token_err!(lex.loc, t); }
-                                }
-                            },
-                            t @ _ => { unexpected_token_err!(lex.loc, t); }
-                        }
-                    }
-                },
-                t @ _ => { unexpected_token_err!(lex.loc, t); }
+            // parameters: `int` or `x: int`
+            let params = parse_list(lex, |lex| {
+                if let Token::Ident(_) = lex.expect_peek_any()? {
+                    lex.next_any()?;
+                    lex.expect_punct(Punct::Colon)?;
+                }
+                parse_type(lex)
+            })?;
+
+            StmtKind::ExternFnDecl {
+                name,
+                params,
+                result: parse_result(lex)?
             }
+        },
 
-            Stmt {
-                loc, kind: StmtKind::ExternFnDecl {
-                    name,
-                    param_count
-                }
+        // `const NAME = expr`
+        Token::Keyword(Keyword::Const) => {
+            lex.next_any()?;
+            let name = match lex.expect_any()? {
+                Token::Ident(name) => name,
+                t => { return Err(unexpected_token_err!(lex.span(), t)); }
+            };
+            lex.expect_punct(Punct::Eq)?;
+            StmtKind::ConstDecl { name, expr: parse_expr(lex, 0)? }
+        },
+
+        // `struct Pos { x: int, y: int, z: int }`
+        Token::Keyword(Keyword::Struct) => {
+            lex.next_any()?;
+            let name = lex.expect_ident()?;
+            let fields = parse_fields(lex, |lex| {
+                let name = parse_name(lex)?;
+                lex.expect_punct(Punct::Colon)?;
+                Ok(Param { name, ty: parse_type(lex)? })
+            })?;
+            StmtKind::StructDecl { name, fields }
+        },
+
+        Token::Keyword(Keyword::Import) => {
+            lex.next_any()?;
+            match lex.expect_any()? {
+                Token::StrLit(path) => StmtKind::Import(path),
+                t => { return Err(unexpected_token_err!(lex.span(), t)); }
             }
         },
 
         Token::Keyword(Keyword::Fn) => {
-            lex.next_any();
+            lex.next_any()?;
 
-            let name = lex.expect_ident();
+            let name = lex.expect_ident()?;
 
-            // parameters
-            lex.expect_punct(Punct::OpenParen);
-            let mut params: Vec<&str> = Vec::new();
-            match lex.expect_any() {
-                Token::Punct(Punct::CloseParen) => {},
-                Token::Ident(param_name) => {
-                    params.push(param_name);
-                    loop {
-                        match lex.expect_any() {
-                            Token::Punct(Punct::CloseParen) => break,
-                            Token::Punct(Punct::Comma) => {
-                                params.push(lex.expect_ident());
-                            },
-                            t @ _ => { unexpected_token_err!(lex.loc, t); }
-                        }
-                    }
-                },
-                t @ _ => { unexpected_token_err!(lex.loc, t); }
-            }
+            // parameters: `x: int`, the type defaults to `int`
+            let params = parse_list(lex, |lex| {
+                let name = match lex.expect_any()? {
+                    Token::Ident(name) => name,
+                    t => { return Err(unexpected_token_err!(lex.span(), t)); }
+                };
+                let mut ty = DataType::Int;
+                if lex.expect_peek_any()? == Token::Punct(Punct::Colon) {
+                    lex.next_any()?;
+                    ty = parse_type(lex)?;
+                }
+                Ok(Param { name, ty })
+            })?;
 
-            // result
-            let has_result = match lex.expect_peek_any() {
-                Token::Punct(Punct::OpenCurly) => false,
-                Token::Keyword(Keyword::Int) => {
-                    lex.next_any();
-                    true
-                },
-                t @ _ => { unexpected_token_err!(lex.loc, t); }
+            StmtKind::FnDecl(FnDecl {
+                name,
+                params,
+                result: parse_result(lex)?,
+                body: parse_block(lex)?,
+                entry: None,
+            })
+        },
+
+        // `#[tick] fn update() { }`
+        Token::Punct(Punct::Attr) => {
+            lex.next_any()?;
+            let name = lex.expect_ident()?;
+            let entry = match name {
+                "load" => Entry::Load,
+                "tick" => Entry::Tick,
+                _ => { return Err(syntax_err!(lex.span(), "Unknown attribute `{name}`")); }
             };
+            lex.expect_punct(Punct::CloseBracket)?;
 
-            Stmt {
-                loc, kind: StmtKind::FnDecl(FnDecl {
-                    name,
-                    params,
-                    has_result,
-                    body: parse_block(lex),
-                })
+            lex.expect_peek_any()?;
+            let start = lex.loc.clone();
+            match parse_stmt_kind(lex)? {
+                StmtKind::FnDecl(FnDecl { entry: Some(_), .. }) => {
+                    return Err(syntax_err!(lex.span_from(start), "A function can only have one attribute"));
+                },
+                StmtKind::FnDecl(data) => StmtKind::FnDecl(FnDecl { entry: Some(entry), ..data }),
+                _ => { return Err(syntax_err!(lex.span_from(start), "`#[{entry}]` can only be put on a function")); }
             }
         },
 
         Token::Keyword(Keyword::For) => {
-            lex.next_any();
+            lex.next_any()?;
 
             let mut init: Option<Box<Stmt>> = None;
             let mut cond: Option<Expr>      = None;
             let mut post: Option<Box<Stmt>> = None;
 
-            match lex.expect_peek_any() {
-                Token::Punct(Punct::Semicolon) => { lex.next_any(); },
+            match lex.expect_peek_any()? {
+                Token::Punct(Punct::Semicolon) => { lex.next_any()?; },
                 _ => {
-                    init = Some(Box::new(parse_stmt(lex)));
-                    lex.expect_punct(Punct::Semicolon);
+                    init = Some(Box::new(parse_stmt(lex)?));
+                    lex.expect_punct(Punct::Semicolon)?;
                 }
             }
 
-            match lex.expect_peek_any() {
-                Token::Punct(Punct::Semicolon) => { lex.next_any(); },
+            match lex.expect_peek_any()? {
+                Token::Punct(Punct::Semicolon) => { lex.next_any()?; },
                 _ => {
-                    cond = Some(parse_expr(lex, 0));
-                    lex.expect_punct(Punct::Semicolon);
+                    cond = Some(parse_cond(lex)?);
+                    lex.expect_punct(Punct::Semicolon)?;
                 }
             }
 
-            match lex.expect_peek_any() {
+            match lex.expect_peek_any()? {
                 Token::Punct(Punct::OpenCurly) => {},
-                _ => { post = Some(Box::new(parse_stmt(lex))); }
+                _ => { post = Some(Box::new(parse_stmt(lex)?)); }
             }
 
-            Stmt {
-                loc, kind: StmtKind::For {
-                    init, cond, post,
-                    body: parse_block(lex)
-                }
+            StmtKind::For {
+                init, cond, post,
+                body: parse_block(lex)?
             }
         },
 
+        Token::Keyword(Keyword::While) => {
+            lex.next_any()?;
+            StmtKind::While {
+                cond: parse_cond(lex)?,
+                body: parse_block(lex)?
+            }
+        },
+
+        Token::Keyword(Keyword::Loop) => {
+            lex.next_any()?;
+            StmtKind::Loop(parse_block(lex)?)
+        },
+
         Token::Punct(Punct::At) => {
-            lex.next_any();
+            lex.next_any()?;
 
-            let name = lex.expect_ident();
-            match lex.expect_any() {
+            let name = lex.expect_ident()?;
+            if name == "print" {
+                return Ok(StmtKind::Print(parse_expr(lex, 0)?));
+            }
+            let level = if name == "log" { Some(LogLevel::Info) } else { LogLevel::from_name(name) };
+            if let Some(level) = level {
+                let format = match lex.expect_any()? {
+                    Token::StrLit(lit) => lit,
+                    t => { return Err(unexpected_token_err!(lex.span(), t)); }
+                };
+
+                // the values follow only a format with placeholders
+                let mut args = Vec::new();
+                if format.contains("{}") {
+                    args.push(parse_expr(lex, 0)?);
+                    while lex.peek_any()? == Some(Token::Punct(Punct::Comma)) {
+                        lex.next_any()?;
+                        args.push(parse_expr(lex, 0)?);
+                    }
+                }
+                return Ok(StmtKind::Log { level, format, args });
+            }
+            match lex.expect_any()? {
                 Token::StrLit(lit) => {
-                    Stmt {
-                        loc, kind: StmtKind::BuilinFnCall {
-                            name, arg: lit
-                        }
+                    StmtKind::BuilinFnCall {
+                        name, arg: lit
                     }
                 },
 
-                t @ _ => {
-                    unexpected_token_err!(lex.loc, t);
+                t => {
+                    return Err(unexpected_token_err!(lex.span(), t));
                 }
             }
         },
 
         Token::Keyword(Keyword::Continue) => {
-            lex.next_any();
-            Stmt { loc, kind: StmtKind::Continue }
+            lex.next_any()?;
+            StmtKind::Continue
         }
 
         Token::Keyword(Keyword::Break) => {
-            lex.next_any();
-            Stmt { loc, kind: StmtKind::Break }
+            lex.next_any()?;
+            StmtKind::Break
+        },
+
+        Token::Keyword(Keyword::Yield) => {
+            lex.next_any()?;
+            StmtKind::Yield
+        },
+
+        Token::Keyword(Keyword::Sleep) => {
+            lex.next_any()?;
+            lex.expect_punct(Punct::OpenParen)?;
+            let ticks = parse_expr(lex, 0)?;
+            lex.expect_punct(Punct::CloseParen)?;
+            StmtKind::Sleep(ticks)
         },
 
         Token::Keyword(Keyword::If) => {
-            lex.next_any();
+            lex.next_any()?;
 
-            let cond = parse_expr(lex, 0);
-            let then = parse_block(lex);
+            let cond = parse_cond(lex)?;
+            let then = parse_block(lex)?;
             let mut elzeifs: Vec<ElseIf> = Vec::new();
             let mut elze: Block = Block::new();
 
-            while lex.expect_peek_any() == Token::Keyword(Keyword::Else) {
-                lex.next_any();
-                if lex.expect_peek_any() == Token::Keyword(Keyword::If) {
-                    lex.next_any();
+            while lex.expect_peek_any()? == Token::Keyword(Keyword::Else) {
+                lex.next_any()?;
+                if lex.expect_peek_any()? == Token::Keyword(Keyword::If) {
+                    lex.next_any()?;
                     elzeifs.push(ElseIf {
-                        cond: parse_expr(lex, 0),
-                        then: parse_block(lex),
+                        cond: parse_cond(lex)?,
+                        then: parse_block(lex)?,
                     });
                 } else {
-                    elze = parse_block(lex);
+                    elze = parse_block(lex)?;
                     break;
                 }
             }
 
-            Stmt {
-                loc, kind: StmtKind::If {
-                    cond, then, elzeifs, elze
-                }
+            StmtKind::If {
+                cond, then, elzeifs, elze
             }
         },
 
         Token::Keyword(Keyword::Return) => {
-            lex.next_any();
-            if let Token::Punct(Punct::CloseCurly) = lex.expect_peek_any() {
-                Stmt {
-                    loc: lex.loc.clone(),
-                    kind: StmtKind::Return
-                }
+            lex.next_any()?;
+            if let Token::Punct(Punct::CloseCurly) = lex.expect_peek_any()? {
+                StmtKind::Return
             } else {
-                let expr = parse_expr(lex, 0);
-                Stmt {
-                    loc: lex.loc.clone(),
-                    kind: StmtKind::ReturnVal(expr)
-                }
+                StmtKind::ReturnVal(parse_expr(lex, 0)?)
             }
         },
 
         Token::Ident(var_name) => {
-            lex.next_any();
-            match lex.expect_peek_any() {
+            lex.next_any()?;
+            let var_name = parse_path(lex, var_name)?;
+            let declared = |lex: &Lexer| if var_name.contains("::") {
+                Err(syntax_err!(lex.span(), "Qualified name `{var_name}` cannot be declared"))
+            } else {
+                Ok(())
+            };
+
+            match lex.expect_peek_any()? {
+                // `x := expr`, `x: bool = expr` or `x: bool`
                 Token::Punct(Punct::Colon) => {
-                    lex.next_any();
-                    lex.expect_punct(Punct::Eq);
-                    let expr = parse_expr(lex, 0);
-                    Stmt {
-                        loc,
-                        kind: StmtKind::VarDeclAssign {
-                            name: var_name, expr,
-                        }
+                    declared(lex)?;
+                    lex.next_any()?;
+                    let ty = match lex.expect_peek_any()? {
+                        Token::Punct(Punct::Eq) => None,
+                        _ => Some(parse_type(lex)?),
+                    };
+
+                    match (ty, lex.peek_any()?) {
+                        (Some(ty), next) if next != Some(Token::Punct(Punct::Eq)) => StmtKind::VarDecl(var_name, ty),
+                        (ty, _) => {
+                            lex.expect_punct(Punct::Eq)?;
+                            StmtKind::VarDeclAssign {
+                                name: var_name, ty,
+                                expr: parse_expr(lex, 0)?,
+                            }
+                        },
                     }
                 },
 
                 Token::Punct(Punct::Eq) => {
-                    lex.next_any();
-                    let expr = parse_expr(lex, 0);
-                    Stmt {
-                        loc,
-                        kind: StmtKind::VarAssign {
-                            name: var_name,
-                            expr 
-                        }
+                    lex.next_any()?;
+                    let expr = parse_expr(lex, 0)?;
+                    StmtKind::VarAssign {
+                        name: var_name,
+                        expr
+                    }
+                },
+
+                Token::Punct(Punct::Dot) => {
+                    let fields = parse_fields_access(lex)?;
+                    lex.expect_punct(Punct::Eq)?;
+                    StmtKind::FieldAssign {
+                        name: var_name, fields,
+                        expr: parse_expr(lex, 0)?
+                    }
+                },
+
+                Token::Punct(Punct::OpenBracket) => {
+                    lex.next_any()?;
+                    let index = parse_expr(lex, 0)?;
+                    lex.expect_punct(Punct::CloseBracket)?;
+                    lex.expect_punct(Punct::Eq)?;
+                    StmtKind::IndexAssign {
+                        name: var_name, index,
+                        expr: parse_expr(lex, 0)?
+                    }
+                },
+
+                Token::AssignOp(op) => {
+                    lex.next_any()?;
+                    StmtKind::CompoundAssign {
+                        name: var_name, op,
+                        expr: parse_expr(lex, 0)?
+                    }
+                },
+
+                Token::Punct(p @ (Punct::Incr | Punct::Decr)) => {
+                    lex.next_any()?;
+                    StmtKind::CompoundAssign {
+                        name: var_name,
+                        op: if p == Punct::Incr { BinOpKind::Add } else { BinOpKind::Sub },
+                        expr: Expr { span: lex.span(), kind: ExprKind::Num(1) }
                     }
                 },
 
                 Token::Punct(Punct::OpenParen) => {
-                    lex.next_any();
+                    lex.next_any()?;
 
                     let mut args: Vec<Expr> = Vec::new();
-                    if lex.expect_peek_any() == Token::Punct(Punct::CloseParen) {
-                        lex.next_any();
-                        return Stmt {
-                            loc, kind: StmtKind::FnCall {
-                                args, name: var_name
-                            }
-                        };
+                    if lex.expect_peek_any()? == Token::Punct(Punct::CloseParen) {
+                        lex.next_any()?;
+                        return Ok(StmtKind::FnCall {
+                            args, name: var_name
+                        });
                     }
 
                     loop {
-                        args.push(parse_expr(lex, 0));
-                        match lex.expect_any() {
+                        args.push(parse_expr(lex, 0)?);
+                        match lex.expect_any()? {
                             Token::Punct(Punct::Comma) => {},
                             Token::Punct(Punct::CloseParen) => break,
-                            t @ _ => { unexpected_token_err!(lex.loc, t); }
+                            t => { return Err(unexpected_token_err!(lex.span(), t)); }
                         }
                     }
 
-                    Stmt {
-                        loc, kind: StmtKind::FnCall {
-                            args, name: var_name
-                        }
+                    StmtKind::FnCall {
+                        args, name: var_name
                     }
                 },
 
-                _ => Stmt { loc, kind: StmtKind::VarDecl(var_name) }
+                _ => {
+                    declared(lex)?;
+                    StmtKind::VarDecl(var_name, DataType::Int)
+                }
             }
         },
 
-        t @ _ => { unexpected_token_err!(lex.loc, t); }
+        t => { return Err(unexpected_token_err!(lex.span(), t)); }
+    })
+}
+
+fn parse_type(lex: &mut Lexer) -> Result<DataType, Diagnostic> {
+    match lex.expect_any()? {
+        Token::Keyword(Keyword::Int)  => Ok(DataType::Int),
+        Token::Keyword(Keyword::Bool) => Ok(DataType::Bool),
+        Token::Keyword(Keyword::Str)  => Ok(DataType::Str),
+        // `[type; length]`
+        Token::Punct(Punct::OpenBracket) => {
+            let elem: &'static DataType = match parse_type(lex)? {
+                DataType::Int  => &DataType::Int,
+                DataType::Bool => &DataType::Bool,
+                ty => { return Err(syntax_err!(lex.span(), "Arrays of `{ty}` are not supported")); }
+            };
+            lex.expect_punct(Punct::Semicolon)?;
+            let len = match lex.expect_any()? {
+                Token::Number(n) if n > 0 => n as usize,
+                Token::Number(n) => { return Err(syntax_err!(lex.span(), "Invalid array length `{n}`")); },
+                t => { return Err(unexpected_token_err!(lex.span(), t)); }
+            };
+            lex.expect_punct(Punct::CloseBracket)?;
+            Ok(DataType::Array(elem, len))
+        },
+        Token::Ident(name) => Ok(DataType::Struct(parse_path(lex, name)?)),
+        t => Err(unexpected_token_err!(lex.span(), t)),
+    }
+}
+
+// `(item, item, ...)`
+fn parse_list<'a, T>(lex: &mut Lexer<'a>, mut item: impl FnMut(&mut Lexer<'a>) -> Result<T, Diagnostic>) -> Result<Vec<T>, Diagnostic> {
+    lex.expect_punct(Punct::OpenParen)?;
+    let mut items = Vec::new();
+    if lex.expect_peek_any()? == Token::Punct(Punct::CloseParen) {
+        lex.next_any()?;
+        return Ok(items);
+    }
+
+    loop {
+        items.push(item(lex)?);
+        match lex.expect_any()? {
+            Token::Punct(Punct::Comma) => {},
+            Token::Punct(Punct::CloseParen) => break,
+            t => { return Err(unexpected_token_err!(lex.span(), t)); }
+        }
+    }
+    Ok(items)
+}
+
+// `{ item, item, ... }`, the trailing comma is optional
+fn parse_fields<'a, T>(lex: &mut Lexer<'a>, mut item: impl FnMut(&mut Lexer<'a>) -> Result<T, Diagnostic>) -> Result<Vec<T>, Diagnostic> {
+    lex.expect_punct(Punct::OpenCurly)?;
+    let mut items = Vec::new();
+    while lex.expect_peek_any()? != Token::Punct(Punct::CloseCurly) {
+        items.push(item(lex)?);
+        if lex.expect_peek_any()? != Token::Punct(Punct::Comma) { break; }
+        lex.next_any()?;
+    }
+    lex.expect_punct(Punct::CloseCurly)?;
+    Ok(items)
+}
+
+// `expect_ident` can't be used after a peek
+fn parse_name(lex: &mut Lexer) -> Result<&'static str, Diagnostic> {
+    match lex.expect_any()? {
+        Token::Ident(name) => Ok(name),
+        t => Err(unexpected_token_err!(lex.span(), t)),
+    }
+}
+
+// `.field.field`
+fn parse_fields_access(lex: &mut Lexer) -> Result<Vec<&'static str>, Diagnostic> {
+    let mut fields = Vec::new();
+    while lex.peek_any()? == Some(Token::Punct(Punct::Dot)) {
+        lex.next_any()?;
+        fields.push(parse_name(lex)?);
+    }
+    Ok(fields)
+}
+
+// `-> type`, the arrow may be omitted
+fn parse_result(lex: &mut Lexer) -> Result<Option<DataType>, Diagnostic> {
+    match lex.peek_any()? {
+        Some(Token::Punct(Punct::Arrow)) => {
+            lex.next_any()?;
+            Ok(Some(parse_type(lex)?))
+        },
+        Some(Token::Keyword(Keyword::Int | Keyword::Bool | Keyword::Str)) => Ok(Some(parse_type(lex)?)),
+        _ => Ok(None),
+    }
+}
+
+// `name` or `module::name`
+fn parse_path(lex: &mut Lexer, name: &'static str) -> Result<&'static str, Diagnostic> {
+    if lex.peek_any()? != Some(Token::Punct(Punct::PathSep)) {
+        return Ok(name);
     }
+
+    lex.next_any()?;
+    let item = lex.expect_ident()?;
+    lex.path(name, item).ok_or_else(|| {
+        syntax_err!(lex.span(), "Qualified name `{name}::{item}` cannot contain whitespace")
+    })
 }
 
-fn parse_block<'a>( lex: &mut Lexer<'a>) -> Block<'a> {
+fn parse_block<'a>(lex: &mut Lexer<'a>) -> Result<Block<'a>, Diagnostic> {
     let mut block = Block::new();
 
-    lex.expect_punct(Punct::OpenCurly);
-    while lex.expect_peek_any() != Token::Punct(Punct::CloseCurly) {
-        block.push(parse_stmt(lex));
+    lex.expect_punct(Punct::OpenCurly)?;
+    while lex.expect_peek_any()? != Token::Punct(Punct::CloseCurly) {
+        block.push(parse_stmt(lex)?);
     }
-    lex.expect_punct(Punct::CloseCurly);
+    lex.expect_punct(Punct::CloseCurly)?;
 
-    block
+    Ok(block)
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogLevel::Debug => write!(f, "debug"),
+            LogLevel::Info  => write!(f, "info"),
+            LogLevel::Warn  => write!(f, "warn"),
+            LogLevel::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Load => write!(f, "load"),
+            Entry::Tick => write!(f, "tick"),
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Int  => write!(f, "int"),
+            DataType::Bool => write!(f, "bool"),
+            DataType::Str  => write!(f, "str"),
+            DataType::Array(elem, len) => write!(f, "[{elem}; {len}]"),
+            DataType::Struct(name) => write!(f, "{name}"),
+        }
+    }
 }
 
 impl fmt::Display for Expr {
@@ -375,11 +765,24 @@ impl fmt::Display for Expr {
         match &self.kind {
             ExprKind::Var(nam) => write!(f, "{nam}"),
             ExprKind::Num(n)   => write!(f, "{n}"),
+            ExprKind::Bool(b)  => write!(f, "{b}"),
+            ExprKind::Str(s)   => write!(f, "\"{s}\""),
             ExprKind::BinOp(data) => {
                 write!(f, "[{} ", data.op)?;
                 write!(f, "{} ", data.lhs)?;
                 write!(f, "{}]", data.rhs)
             },
+            ExprKind::UnaryOp(data) => write!(f, "[{} {}]", data.op, data.expr),
+            ExprKind::Index(data) => write!(f, "{}[{}]", data.name, data.index),
+            ExprKind::Field(data) => write!(f, "{}.{}", data.name, data.fields.join(".")),
+            ExprKind::Struct(data) => {
+                let fields: Vec<String> = data.fields.iter().map(|(name, e)| format!("{name}: {e}")).collect();
+                write!(f, "{} {{ {} }}", data.name, fields.join(", "))
+            },
+            ExprKind::Array(elems) => {
+                let elems: Vec<String> = elems.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elems.join(", "))
+            },
             ExprKind::FnCall(data) => {
                 write!(f, "{}(", data.name)?;
                 write!(f, "{}", data.args[0])?;
@@ -392,80 +795,143 @@ impl fmt::Display for Expr {
     }
 }
 
-pub fn parse_expr(lex: &mut Lexer, prec: u8) -> Expr {
+pub fn parse_expr(lex: &mut Lexer, prec: u8) -> Result<Expr, Diagnostic> {
+    parse_expr_in(lex, prec, true)
+}
+
+// struct literals aren't allowed in conditions, `if p == q {` opens the block
+fn parse_cond(lex: &mut Lexer) -> Result<Expr, Diagnostic> {
+    parse_expr_in(lex, 0, false)
+}
+
+fn parse_expr_in(lex: &mut Lexer, prec: u8, structs: bool) -> Result<Expr, Diagnostic> {
     // the implementation based on the Pratt Parsing algorithm
     let token: Token;
-    let mut lhs = match lex.expect_any() {
-        Token::BinOp(BinOpKind::Sub) => {
-            match lex.expect_any() {
-                Token::Number(n) => Expr {
-                    loc: lex.loc.clone(),
-                    kind: ExprKind::Num(-n)
-                },
-                t @ _ => { unexpected_token_err!(lex.loc, t); }
-            }
-        },
-        Token::Number(n) => Expr { loc: lex.loc.clone(), kind: ExprKind::Num(n) },
+    let mut lhs = match lex.expect_any()? {
+        Token::BinOp(BinOpKind::Sub) => parse_unary(lex, UnOpKind::Neg, structs)?,
+        Token::UnOp(op) => parse_unary(lex, op, structs)?,
+        Token::Number(n) => Expr { span: lex.span(), kind: ExprKind::Num(n) },
+        Token::Keyword(Keyword::True)  => Expr { span: lex.span(), kind: ExprKind::Bool(true) },
+        Token::Keyword(Keyword::False) => Expr { span: lex.span(), kind: ExprKind::Bool(false) },
+        Token::StrLit(s) => Expr { span: lex.span(), kind: ExprKind::Str(s) },
         Token::Ident(name) => {
-            if lex.expect_peek_any() == Token::Punct(Punct::OpenParen) {
-                lex.next_any();
+            let span = lex.span();
+            let name = parse_path(lex, name)?;
+            if lex.expect_peek_any()? == Token::Punct(Punct::OpenParen) {
+                lex.next_any()?;
                 let mut args: Vec<Expr> = Vec::new();
-                if lex.expect_peek_any() == Token::Punct(Punct::CloseParen) {
-                    lex.next_any();
+                if lex.expect_peek_any()? == Token::Punct(Punct::CloseParen) {
+                    lex.next_any()?;
                 } else {
-                    args.push(parse_expr(lex, 0));
-                    while lex.expect_any() != Token::Punct(Punct::CloseParen) {
-                        args.push(parse_expr(lex, 0));
+                    args.push(parse_expr(lex, 0)?);
+                    while lex.expect_any()? != Token::Punct(Punct::CloseParen) {
+                        args.push(parse_expr(lex, 0)?);
                     }
                 }
 
                 Expr {
-                    loc: lex.loc.clone(),
+                    span: lex.span_from(span.start),
                     kind: ExprKind::FnCall(Box::new(FnCallExpr {
                         name, args
                     }))
                 }
+            } else if lex.expect_peek_any()? == Token::Punct(Punct::OpenBracket) {
+                lex.next_any()?;
+                let index = parse_expr(lex, 0)?;
+                lex.expect_punct(Punct::CloseBracket)?;
+                Expr {
+                    span: lex.span_from(span.start),
+                    kind: ExprKind::Index(Box::new(IndexExpr { name, index }))
+                }
+            } else if lex.expect_peek_any()? == Token::Punct(Punct::Dot) {
+                let fields = parse_fields_access(lex)?;
+                Expr {
+                    span: lex.span_from(span.start),
+                    kind: ExprKind::Field(Box::new(FieldExpr { name, fields }))
+                }
+            } else if structs && lex.expect_peek_any()? == Token::Punct(Punct::OpenCurly) {
+                let fields = parse_fields(lex, |lex| {
+                    let field = parse_name(lex)?;
+                    lex.expect_punct(Punct::Colon)?;
+                    Ok((field, parse_expr(lex, 0)?))
+                })?;
+                Expr {
+                    span: lex.span_from(span.start),
+                    kind: ExprKind::Struct(Box::new(StructExpr { name, fields }))
+                }
             } else {
                 Expr {
-                    loc: lex.loc.clone(),
+                    span,
                     kind: ExprKind::Var(name)
                 }
             }
         },
+        Token::Punct(Punct::OpenBracket) => {
+            let start = lex.span().start;
+            let mut elems = Vec::new();
+            if lex.expect_peek_any()? == Token::Punct(Punct::CloseBracket) {
+                lex.next_any()?;
+            } else {
+                loop {
+                    elems.push(parse_expr(lex, 0)?);
+                    match lex.expect_any()? {
+                        Token::Punct(Punct::Comma) => {},
+                        Token::Punct(Punct::CloseBracket) => break,
+                        t => { return Err(unexpected_token_err!(lex.span(), t)); }
+                    }
+                }
+            }
+            Expr { span: lex.span_from(start), kind: ExprKind::Array(elems) }
+        },
         Token::Punct(Punct::OpenParen) => {
-            let lhs = parse_expr(lex, 0);
-            token = lex.expect_any();
+            let start = lex.loc.clone();
+            let mut lhs = parse_expr(lex, 0)?;
+            token = lex.expect_any()?;
             if token != Token::Punct(Punct::CloseParen) {
-                unexpected_token_err!(lex.loc, token);
+                return Err(unexpected_token_err!(lex.span(), token));
             }
+            lhs.span = lex.span_from(start);
             lhs
         },
-        t @ _ => { unexpected_token_err!(lex.loc, t); }
+        t => { return Err(unexpected_token_err!(lex.span(), t)); }
     };
 
-    loop {
-        match lex.expect_peek_any() {
-            Token::BinOp(kind) => {
-                let this_prec = bin_op_prec(kind.clone());
-                if  this_prec < prec {
-                    break
-                } else {
-                    lex.next_any();
-                    let rhs = parse_expr(lex, this_prec);
-                    lhs = Expr {
-                        loc: lex.loc.clone(),
-                        kind: ExprKind::BinOp(Box::new(BinOpExpr {
-                            lhs, rhs, op:  kind
-                        }))
-                    }
-                }
-            },
+    while let Token::BinOp(kind) = lex.expect_peek_any()? {
+        let this_prec = bin_op_prec(kind.clone());
+        if  this_prec < prec {
+            break
+        }
 
-            _ => break,
+        lex.next_any()?;
+        // binary operators are left-associative
+        let rhs = parse_expr_in(lex, this_prec+1, structs)?;
+        lhs = Expr {
+            span: lhs.span.to(&rhs.span),
+            kind: ExprKind::BinOp(Box::new(BinOpExpr {
+                lhs, rhs, op:  kind
+            }))
         }
     }
 
-    lhs
+    Ok(lhs)
+}
+
+// unary operators bind tighter than any binary one
+const UNARY_PREC: u8 = 6;
+
+fn parse_unary(lex: &mut Lexer, op: UnOpKind, structs: bool) -> Result<Expr, Diagnostic> {
+    let start = lex.span();
+    let expr = parse_expr_in(lex, UNARY_PREC, structs)?;
+    let span = start.to(&expr.span);
+    Ok(match (op, expr.kind) {
+        (UnOpKind::Neg, ExprKind::Num(n)) => Expr { span, kind: ExprKind::Num(n.wrapping_neg()) },
+        (op, kind) => Expr {
+            span,
+            kind: ExprKind::UnaryOp(Box::new(UnaryOpExpr {
+                op, expr: Expr { span: expr.span, kind }
+            }))
+        },
+    })
 }
 
 fn bin_op_prec(bin_op_kind: BinOpKind) -> u8 {
@@ -482,4 +948,88 @@ fn bin_op_prec(bin_op_kind: BinOpKind) -> u8 {
     }
 }
 
-// TODO: make tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expr(src: &str) -> String {
+        // the expression must be followed by some token
+        let src = format!("{src};");
+        parse_expr(&mut Lexer::new(src.as_bytes(), 0), 0).unwrap().to_string()
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(expr("a - b - c"), "[- [- a b] c]");
+        assert_eq!(expr("a / b * c + d"), "[+ [* [/ a b] c] d]");
+        assert_eq!(expr("-x * 2"), "[* [- x] 2]");
+        assert_eq!(expr("-(a + b) - -3"), "[- [- [+ a b]] -3]");
+        assert_eq!(expr("!(a && b) || ~c == 0"), "[|| [! [&& a b]] [== [~ c] 0]]");
+        assert_eq!(expr("!!f(x)"), "[! [! f(x)]]");
+    }
+
+    #[test]
+    fn structs() {
+        assert_eq!(expr("Pos { x: a.x + 1, y: 2, } == b"), "[== Pos { x: [+ a.x 1], y: 2 } b]");
+        assert_eq!(expr("line.start.y * 2"), "[* line.start.y 2]");
+
+        let mut lexer = Lexer::new(b"if p.x == q { p = Pos { x: 1 } };", 0);
+        let StmtKind::If { cond, then, .. } = parse_stmt(&mut lexer).unwrap().kind else { unreachable!() };
+        assert_eq!(cond.to_string(), "[== p.x q]");
+        assert!(matches!(&then[0].kind, StmtKind::VarAssign { name: "p", expr } if expr.to_string() == "Pos { x: 1 }"));
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(expr("\"Built \" + n * 2 + \" blocks\""), "[+ [+ \"Built \" [* n 2]] \" blocks\"]");
+
+        let mut lexer = Lexer::new(b"@print \"hi \" + name;", 0);
+        let StmtKind::Print(text) = parse_stmt(&mut lexer).unwrap().kind else { unreachable!() };
+        assert_eq!(text.to_string(), "[+ \"hi \" name]");
+    }
+
+    #[test]
+    fn attributes() {
+        let mut lexer = Lexer::new(b"#[tick] fn update() {}", 0);
+        let StmtKind::FnDecl(data) = parse_stmt(&mut lexer).unwrap().kind else { unreachable!() };
+        assert_eq!((data.name, data.entry), ("update", Some(Entry::Tick)));
+
+        let mut lexer = Lexer::new(b"#[every] fn f() {}", 0);
+        assert_eq!(parse_stmt(&mut lexer).unwrap_err().message, "Unknown attribute `every`");
+        let mut lexer = Lexer::new(b"#[load] #[tick] fn f() {}", 0);
+        assert_eq!(parse_stmt(&mut lexer).unwrap_err().message, "A function can only have one attribute");
+        let mut lexer = Lexer::new(b"#[load] extern f()", 0);
+        assert_eq!(parse_stmt(&mut lexer).unwrap_err().message, "`#[load]` can only be put on a function");
+    }
+
+    #[test]
+    fn log_statements() {
+        let mut lexer = Lexer::new(b"@log \"x={} y={}\" x, y+1;", 0);
+        let StmtKind::Log { level, format, args } = parse_stmt(&mut lexer).unwrap().kind else { unreachable!() };
+        assert_eq!((level, format), (LogLevel::Info, "x={} y={}"));
+        assert_eq!(args.iter().map(|a| a.to_string()).collect::<Vec<_>>(), ["x", "[+ y 1]"]);
+
+        let mut lexer = Lexer::new(b"@warn \"done\"; x = 1;", 0);
+        let StmtKind::Log { level, args, .. } = parse_stmt(&mut lexer).unwrap().kind else { unreachable!() };
+        assert_eq!((level, args.len()), (LogLevel::Warn, 0));
+        assert!(LogLevel::Debug < LogLevel::Error);
+    }
+
+    #[test]
+    fn cmd_interpolation() {
+        assert_eq!(cmd_parts("setblock {x} {y} {geom::z} stone"), [
+            CmdPart::Text("setblock "),
+            CmdPart::Var("x"),
+            CmdPart::Text(" "),
+            CmdPart::Var("y"),
+            CmdPart::Text(" "),
+            CmdPart::Var("geom::z"),
+            CmdPart::Text(" stone"),
+        ]);
+        assert_eq!(cmd_parts("summon zombie ~ ~ ~ {NoAI:1b,Tags:[\"a\"]}"), [
+            CmdPart::Text("summon zombie ~ ~ ~ {NoAI:1b,Tags:[\"a\"]}"),
+        ]);
+        assert_eq!(cmd_parts("{{x}}"), [CmdPart::Text("{"), CmdPart::Var("x"), CmdPart::Text("}")]);
+        assert_eq!(cmd_parts("tp {pos.x} {a.}"), [CmdPart::Text("tp "), CmdPart::Var("pos.x"), CmdPart::Text(" {a.}")]);
+    }
+}
@@ -1,7 +1,6 @@
 use std::collections::HashMap;
 
-use crate::parser::*;
-use super::{semantic_err, exit_failure};
+use crate::{diagnostic::Diagnostic, lexer::{BinOpKind, Span, UnOpKind}, parser::*, semantic_err, semantic_warn};
 
 pub type Name<'a> = &'a str;
 pub type SP2      = usize;
@@ -13,49 +12,167 @@ pub struct Scope<'a> {
     pub parent: ScopeIdx
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FnDeclInfo {
-    pub has_result:  bool,
-    pub param_count: usize,
+    pub result:      Option<DataType>,
+    pub params:      Vec<DataType>,
     pub local_count: usize,
+    pub span:        Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name:   String,
+    pub ty:     DataType,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructInfo {
+    pub fields: Vec<Field>,
+    // the number of slots, nested structs are stored inline
+    pub size:   usize,
 }
 
 #[derive(Debug)]
 pub enum Type {
-    Var(SP2),
+    // the slot is a global index for variables of the global scope,
+    // structs occupy `size` consecutive slots starting from it
+    Var(SP2, DataType),
+    // values of constants are folded into expressions by `fold::fold`
+    Const(DataType),
     FnDecl(FnDeclInfo),
+    Struct(StructInfo),
+}
+
+pub fn struct_info<'s>(scopes: &'s [Scope], name: &str) -> Option<&'s StructInfo> {
+    match scopes[0].items.get(name) {
+        Some(Type::Struct(info)) => Some(info),
+        _ => None,
+    }
+}
+
+// the number of slots taken by a value of the type, an array is one slot
+// that names its list in the storage
+pub fn size_of(scopes: &[Scope], ty: DataType) -> usize {
+    match ty {
+        DataType::Struct(name) => struct_info(scopes, name).map_or(1, |info| info.size),
+        _ => 1,
+    }
+}
+
+// the scalar type of every slot of a value in the order of the slots
+pub fn slots(scopes: &[Scope], ty: DataType) -> Vec<DataType> {
+    match ty {
+        DataType::Struct(name) => struct_info(scopes, name).map_or(vec![ty], |info| {
+            info.fields.iter().flat_map(|f| slots(scopes, f.ty)).collect()
+        }),
+        _ => vec![ty],
+    }
+}
+
+// the offset of `value.a.b` from the first slot of `value` and the type of the field
+pub fn field_offset(scopes: &[Scope], mut ty: DataType, fields: &[&str]) -> Result<(usize, DataType), String> {
+    let mut offset = 0;
+    for name in fields {
+        let DataType::Struct(struct_name) = ty else {
+            return Err(format!("Type `{ty}` has no field `{name}`"));
+        };
+        let field = struct_info(scopes, struct_name)
+            .and_then(|info| info.fields.iter().find(|f| f.name == *name))
+            .ok_or_else(|| format!("Struct `{struct_name}` has no field `{name}`"))?;
+        offset += field.offset;
+        ty = field.ty;
+    }
+    Ok((offset, ty))
+}
+
+// every path through the block ends with `return` or an endless loop
+fn always_returns(block: &[Stmt]) -> bool {
+    block.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Return | StmtKind::ReturnVal(_) => true,
+        StmtKind::If { then, elzeifs, elze, .. } => {
+            !elze.is_empty() && always_returns(then) && always_returns(elze) && elzeifs.iter().all(|e| always_returns(&e.then))
+        },
+        StmtKind::Loop(body) | StmtKind::For { body, cond: None, .. } => !breaks(body),
+        StmtKind::While { cond: Expr { kind: ExprKind::Bool(true), .. }, body } => !breaks(body),
+        _ => false,
+    })
+}
+
+// `break` of the enclosing loop, nested loops have their own
+fn breaks(block: &[Stmt]) -> bool {
+    block.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Break => true,
+        StmtKind::If { then, elzeifs, elze, .. } => breaks(then) || breaks(elze) || elzeifs.iter().any(|e| breaks(&e.then)),
+        _ => false,
+    })
 }
 
 pub struct Analyzer<'a> {
     scopes: Vec<Scope<'a>>,
-    sp2:    SP2   
+    sp2:    SP2,
+    // globals live in `redvm.global`, outside of the stack frames
+    globals: usize,
+    diagnostics: Vec<Diagnostic>,
 }
 
 #[derive(Default)]
 struct Flags {
-    in_loop:    bool,
-    has_result: bool,
+    in_loop: bool,
+    result:  Option<DataType>,
 }
 
 impl<'a> Analyzer<'a> {
-    pub fn analyze(ast: &'a Ast<'a>) -> Vec<Scope<'a>> {
+    // the analysis doesn't stop on errors, so every problem of the file
+    // is reported at once
+    pub fn analyze(ast: &'a Ast<'a>) -> (Vec<Scope<'a>>, Vec<Diagnostic>) {
         let mut analyzer = Self {
             scopes: Vec::new(),
             sp2:    0,
+            globals: 0,
+            diagnostics: Vec::new(),
         };
 
-        analyzer.analyze_block(&ast.stmts, 0, &Flags::default());
+        analyzer.scopes.push(Scope { items: HashMap::new(), parent: 0 });
+        analyzer.declare_fns(&ast.stmts);
+        analyzer.analyze_stmts(&ast.stmts, 0, &Flags::default());
+
+        // a program driven by `#[load]` and `#[tick]` functions doesn't need `main`
+        let has_entry = ast.stmts.iter().any(|s| matches!(&s.kind, StmtKind::FnDecl(FnDecl { entry: Some(_), .. })));
+        if !has_entry && !matches!(analyzer.scopes[0].items.get("main"), Some(Type::FnDecl(_))) {
+            analyzer.diagnostics.push(Diagnostic::error(
+                None, "SemanticError", "Function `main` is not found".to_string()
+            ));
+        }
 
-        analyzer.scopes
+        (analyzer.scopes, analyzer.diagnostics)
     }
 
-    fn name_exists_global(&self, name: Name<'a>, scope: ScopeIdx) -> bool {
-        if self.scopes[scope].items.contains_key(name) {
-            true
-        } else if scope != 0 {
-            self.name_exists_global(name, self.scopes[scope].parent)
-        } else {
-            false
+    // functions can be called before their declaration and recursively,
+    // so the signatures are registered before any body is analyzed
+    fn declare_fns(&mut self, stmts: &'a [Stmt<'a>]) {
+        for stmt in stmts {
+            let (name, params, result) = match &stmt.kind {
+                StmtKind::ExternFnDecl { name, params, result } => (*name, params.clone(), *result),
+                StmtKind::FnDecl(data) => (data.name, data.params.iter().map(|p| p.ty).collect(), data.result),
+                _ => continue,
+            };
+            self.scopes[0].items.entry(name).or_insert(Type::FnDecl(FnDeclInfo {
+                params,
+                result,
+                local_count: 0,
+                span: stmt.span.clone(),
+            }));
+        }
+    }
+
+    // true unless `name` is free or was registered for this declaration
+    fn is_redeclared(&self, name: Name<'a>, span: &Span, scope: ScopeIdx) -> bool {
+        match self.scopes[scope].items.get(name) {
+            Some(Type::FnDecl(info)) => info.span != *span,
+            Some(_) => true,
+            None => false,
         }
     }
 
@@ -66,59 +183,348 @@ impl<'a> Analyzer<'a> {
         })
     }
 
-    fn check_return_value(&mut self, block: &Block<'a>) {
-        let stmt = block.last().unwrap();
-        if !matches!(stmt.kind, StmtKind::ReturnVal(_)) {
-            semantic_err!(stmt.loc, "Return value is missed");
+    fn get_fn_global(&self, name: Name<'a>, scope: ScopeIdx) -> Option<FnDeclInfo> {
+        match self.get_type_global(name, scope) {
+            Some(Type::FnDecl(info)) => Some(info.clone()),
+            _ => None
         }
     }
 
-    fn analyze_expr(&mut self, expr: &Expr, scope: ScopeIdx) {
+    fn get_var_global(&self, name: Name<'a>, scope: ScopeIdx) -> Option<DataType> {
+        match self.get_type_global(name, scope) {
+            Some(Type::Var(_, ty)) | Some(Type::Const(ty)) => Some(*ty),
+            _ => None
+        }
+    }
+
+    fn is_const(&self, name: Name<'a>, scope: ScopeIdx) -> bool {
+        matches!(self.get_type_global(name, scope), Some(Type::Const(..)))
+    }
+
+    fn size_of(&self, ty: DataType) -> usize {
+        size_of(&self.scopes, ty)
+    }
+
+    // strings are joined by macro functions, which can't escape a backslash
+    fn check_str(&mut self, text: &str, span: &Span) {
+        if text.contains('\\') {
+            self.diagnostics.push(semantic_err!(span, "Strings can't contain `\\`"));
+        }
+    }
+
+    // struct types must be declared before they are used
+    fn check_type(&mut self, ty: DataType, span: &Span) {
+        if let DataType::Struct(name) = ty {
+            if struct_info(&self.scopes, name).is_none() {
+                self.diagnostics.push(semantic_err!(span, "Struct `{name}` is not found"));
+            }
+        }
+    }
+
+    // `pos.x` of `@cmd`
+    fn analyze_var_path(&mut self, path: Name<'a>, span: &Span, scope: ScopeIdx) -> Option<DataType> {
+        let mut fields: Vec<&str> = path.split('.').collect();
+        let name = fields.remove(0);
+        let Some(ty) = self.get_var_global(name, scope) else {
+            self.diagnostics.push(semantic_err!(span, "Variable `{name}` is not found"));
+            return None;
+        };
+        self.field_type(ty, &fields, span)
+    }
+
+    fn field_type(&mut self, ty: DataType, fields: &[&str], span: &Span) -> Option<DataType> {
+        match field_offset(&self.scopes, ty, fields) {
+            Ok((_, ty)) => Some(ty),
+            Err(msg) => {
+                self.diagnostics.push(semantic_err!(span, "{msg}"));
+                None
+            },
+        }
+    }
+
+    // returns `None` when the type is unknown because of an earlier error,
+    // so one mistake doesn't produce a chain of mismatches
+    fn analyze_expr(&mut self, expr: &Expr, scope: ScopeIdx) -> Option<DataType> {
         match &expr.kind {
-            ExprKind::Num(_) => {},
+            ExprKind::Num(_)  => Some(DataType::Int),
+            ExprKind::Bool(_) => Some(DataType::Bool),
+            ExprKind::Str(text) => {
+                self.check_str(text, &expr.span);
+                Some(DataType::Str)
+            },
             ExprKind::Var(name) => {
-                if !matches!(self.get_type_global(name, scope), Some(Type::Var(_))){
-                    semantic_err!(expr.loc, "Variable `{name}` is not found");
+                let ty = self.get_var_global(name, scope);
+                match ty {
+                    None => self.diagnostics.push(semantic_err!(expr.span, "Variable `{name}` is not found")),
+                    Some(DataType::Array(..)) => {
+                        self.diagnostics.push(semantic_err!(expr.span, "Array `{name}` can only be indexed"));
+                        return None;
+                    },
+                    _ => {},
                 }
+                ty
             },
 
-            ExprKind::FnCall(data) => {
-                if let Some(Type::FnDecl(fn_decl)) = self.get_type_global(data.name, scope){
-                    if !fn_decl.has_result {
-                        semantic_err!(expr.loc, "Function `{}` doesn't return value", data.name);
+            ExprKind::Index(data) => self.analyze_index(data.name, &data.index, &expr.span, scope),
+
+            ExprKind::Field(data) => {
+                let Some(ty) = self.get_var_global(data.name, scope) else {
+                    self.diagnostics.push(semantic_err!(expr.span, "Variable `{}` is not found", data.name));
+                    return None;
+                };
+                self.field_type(ty, &data.fields, &expr.span)
+            },
+
+            ExprKind::Struct(data) => {
+                let Some(info) = struct_info(&self.scopes, data.name).cloned() else {
+                    self.diagnostics.push(semantic_err!(expr.span, "Struct `{}` is not found", data.name));
+                    for (_, field) in &data.fields { self.analyze_expr(field, scope); }
+                    return None;
+                };
+
+                for (i, (name, value)) in data.fields.iter().enumerate() {
+                    match info.fields.iter().find(|f| f.name == *name) {
+                        Some(field) => self.expect_type(value, field.ty, scope),
+                        None => {
+                            self.diagnostics.push(semantic_err!(value.span, "Struct `{}` has no field `{name}`", data.name));
+                            self.analyze_expr(value, scope);
+                        },
+                    }
+                    if data.fields[..i].iter().any(|(other, _)| other == name) {
+                        self.diagnostics.push(semantic_err!(value.span, "Field `{name}` is set twice"));
+                    }
+                }
+                for field in &info.fields {
+                    if !data.fields.iter().any(|(name, _)| *name == field.name) {
+                        self.diagnostics.push(semantic_err!(expr.span, "Field `{}` of `{}` is not set", field.name, data.name));
                     }
-                    if data.args.len() != fn_decl.param_count {
-                        semantic_err!(expr.loc, "Function `{}`'s arguments are incorrect", data.name);
+                }
+                Some(DataType::Struct(data.name))
+            },
+
+            ExprKind::Array(elems) => {
+                self.diagnostics.push(semantic_err!(expr.span, "Array literals are only allowed as the value of an array variable"));
+                for elem in elems { self.analyze_expr(elem, scope); }
+                None
+            },
+
+            ExprKind::FnCall(data) => {
+                let Some(info) = self.get_fn_global(data.name, scope) else {
+                    self.diagnostics.push(semantic_err!(expr.span, "Function `{}` is not found", data.name));
+                    for arg in &data.args { self.analyze_expr(arg, scope); }
+                    return None;
+                };
+
+                if info.result.is_none() {
+                    self.diagnostics.push(semantic_err!(expr.span, "Function `{}` doesn't return value", data.name));
+                }
+                if data.args.len() != info.params.len() {
+                    self.diagnostics.push(
+                        semantic_err!(expr.span, "Function `{}`'s arguments are incorrect", data.name)
+                            .with_note(Some(info.span.clone()), "function declared here")
+                    );
+                }
+                self.analyze_args(&data.args, &info, scope);
+                info.result
+            },
+
+            // `+` joins strings when either operand is a `str`
+            ExprKind::BinOp(data) if data.op == BinOpKind::Add => {
+                let lhs = self.analyze_expr(&data.lhs, scope);
+                let rhs = self.analyze_expr(&data.rhs, scope);
+                let text = lhs == Some(DataType::Str) || rhs == Some(DataType::Str);
+                for (ty, operand) in [(lhs, &data.lhs), (rhs, &data.rhs)] {
+                    match ty {
+                        _ if text => self.check_text(ty, &operand.span),
+                        Some(ty) if ty != DataType::Int => {
+                            self.diagnostics.push(semantic_err!(operand.span, "Mismatched types: expected `int`, found `{ty}`"));
+                        },
+                        _ => {},
                     }
-                } else {
-                    semantic_err!(expr.loc, "Function `{}` is not found", data.name);
                 }
+                Some(if text { DataType::Str } else { DataType::Int })
             },
 
             ExprKind::BinOp(data) => {
-                self.analyze_expr(&data.lhs, scope);
-                self.analyze_expr(&data.rhs, scope);
+                let (operand, result) = match data.op {
+                    BinOpKind::Add | BinOpKind::Sub | BinOpKind::Mul | BinOpKind::Div | BinOpKind::Mod => (Some(DataType::Int), DataType::Int),
+                    BinOpKind::Gt | BinOpKind::Ge | BinOpKind::Lt | BinOpKind::Le => (Some(DataType::Int), DataType::Bool),
+                    BinOpKind::And | BinOpKind::Or => (Some(DataType::Bool), DataType::Bool),
+                    // both operands must have the same type
+                    BinOpKind::Eq | BinOpKind::Ne => (None, DataType::Bool),
+                };
+
+                match operand {
+                    Some(ty) => {
+                        self.expect_type(&data.lhs, ty, scope);
+                        self.expect_type(&data.rhs, ty, scope);
+                    },
+                    None => {
+                        if let Some(ty) = self.analyze_expr(&data.lhs, scope) {
+                            if let DataType::Struct(_) | DataType::Str = ty {
+                                self.diagnostics.push(semantic_err!(expr.span, "Values of `{ty}` can't be compared"));
+                            }
+                            self.expect_type(&data.rhs, ty, scope);
+                        } else {
+                            self.analyze_expr(&data.rhs, scope);
+                        }
+                    },
+                }
+                Some(result)
+            },
+
+            ExprKind::UnaryOp(data) => {
+                let ty = match data.op {
+                    UnOpKind::Neg | UnOpKind::BitNot => DataType::Int,
+                    UnOpKind::Not => DataType::Bool,
+                };
+                self.expect_type(&data.expr, ty, scope);
+                Some(ty)
+            },
+        }
+    }
+
+    // `str` or `int`, which is converted to its text
+    fn expect_text(&mut self, expr: &Expr, scope: ScopeIdx) {
+        let ty = self.analyze_expr(expr, scope);
+        self.check_text(ty, &expr.span);
+    }
+
+    fn check_text(&mut self, ty: Option<DataType>, span: &Span) {
+        if let Some(ty @ (DataType::Bool | DataType::Array(..) | DataType::Struct(_))) = ty {
+            self.diagnostics.push(semantic_err!(span, "Values of `{ty}` can't be joined to a `str`"));
+        }
+    }
+
+    fn expect_type(&mut self, expr: &Expr, expected: DataType, scope: ScopeIdx) {
+        match self.analyze_expr(expr, scope) {
+            Some(found) if found != expected => {
+                self.diagnostics.push(semantic_err!(expr.span, "Mismatched types: expected `{expected}`, found `{found}`"));
+            },
+            _ => {},
+        }
+    }
+
+    // returns the type of the element
+    fn analyze_index(&mut self, name: Name<'a>, index: &Expr, span: &Span, scope: ScopeIdx) -> Option<DataType> {
+        self.expect_type(index, DataType::Int, scope);
+        match self.get_var_global(name, scope) {
+            Some(DataType::Array(elem, len)) => {
+                if let ExprKind::Num(n) = index.kind {
+                    if n < 0 || n as usize >= len {
+                        self.diagnostics.push(semantic_err!(index.span, "Index {n} is out of bounds for `{name}` of length {len}"));
+                    }
+                }
+                Some(*elem)
+            },
+            Some(ty) => {
+                self.diagnostics.push(semantic_err!(span, "Cannot index `{name}` of type `{ty}`"));
+                None
+            },
+            None => {
+                self.diagnostics.push(semantic_err!(span, "Variable `{name}` is not found"));
+                None
             },
         }
     }
 
+    // array literals are only allowed as the whole value of a variable, the
+    // type of the elements comes from the declaration or the first element
+    fn analyze_value(&mut self, expr: &Expr, expected: Option<DataType>, scope: ScopeIdx) -> Option<DataType> {
+        let ExprKind::Array(elems) = &expr.kind else {
+            return match expected {
+                Some(ty) => {
+                    self.expect_type(expr, ty, scope);
+                    Some(ty)
+                },
+                None => self.analyze_expr(expr, scope),
+            };
+        };
+
+        let Some(first) = elems.first() else {
+            self.diagnostics.push(semantic_err!(expr.span, "Array literal must have at least one element"));
+            return expected;
+        };
+        let elem = match expected {
+            Some(DataType::Array(elem, _)) => Some(*elem),
+            _ => self.analyze_expr(first, scope),
+        };
+        let elem: &'static DataType = match elem {
+            Some(DataType::Int)  => &DataType::Int,
+            Some(DataType::Bool) => &DataType::Bool,
+            Some(ty) => {
+                self.diagnostics.push(semantic_err!(first.span, "Arrays of `{ty}` are not supported"));
+                return expected;
+            },
+            None => return expected,
+        };
+
+        let skip = if matches!(expected, Some(DataType::Array(..))) { 0 } else { 1 };
+        for e in elems.iter().skip(skip) { self.expect_type(e, *elem, scope); }
+
+        let found = DataType::Array(elem, elems.len());
+        match expected {
+            Some(ty) if ty != found => {
+                self.diagnostics.push(semantic_err!(expr.span, "Mismatched types: expected `{ty}`, found `{found}`"));
+                Some(ty)
+            },
+            _ => Some(found),
+        }
+    }
+
+    fn check_signature(&mut self, params: &[DataType], result: Option<DataType>, span: &Span) {
+        for ty in params.iter().chain(result.iter()) { self.check_type(*ty, span); }
+        if params.iter().chain(result.iter()).any(|ty| matches!(ty, DataType::Array(..))) {
+            self.diagnostics.push(semantic_err!(span, "Arrays can't be passed to or returned from functions"));
+        }
+    }
+
+    fn analyze_args(&mut self, args: &[Expr], info: &FnDeclInfo, scope: ScopeIdx) {
+        for (i, arg) in args.iter().enumerate() {
+            match info.params.get(i) {
+                Some(ty) => self.expect_type(arg, *ty, scope),
+                None => { self.analyze_expr(arg, scope); },
+            }
+        }
+    }
+
     fn analyze_stmt(&mut self, stmt: &'a Stmt<'a>, scope_idx: ScopeIdx, flags: &Flags) {
         match &stmt.kind {
-            StmtKind::ExternFnDecl { name, param_count } => {
-                if self.scopes[scope_idx].items.contains_key(name) {
-                    semantic_err!(stmt.loc, "Redeclaration of function `{}`", name);
+            // imports are linked by `module::Loader` before the analysis
+            StmtKind::Import(_) => {},
+
+            StmtKind::ExternFnDecl { name, params, result } => {
+                if self.is_redeclared(name, &stmt.span, scope_idx) {
+                    self.diagnostics.push(semantic_err!(stmt.span, "Redeclaration of function `{}`", name));
+                    return;
+                }
+                self.check_signature(params, *result, &stmt.span);
+                // extern functions return a single score
+                if let Some(ty @ (DataType::Struct(_) | DataType::Str)) = result {
+                    self.diagnostics.push(semantic_err!(stmt.span, "Extern functions can't return `{ty}`"));
                 }
 
                 self.scopes[scope_idx].items.insert(name, Type::FnDecl(FnDeclInfo {
-                    param_count: *param_count,
-                    has_result: false,
+                    params: params.clone(),
+                    result: *result,
                     local_count: 0,
+                    span: stmt.span.clone(),
                 }));
             },
 
             StmtKind::FnDecl(data) => {
-                if self.scopes[scope_idx].items.contains_key(data.name) {
-                    semantic_err!(stmt.loc, "Redeclaration of function `{}`", data.name);
+                let redeclared = self.is_redeclared(data.name, &stmt.span, scope_idx);
+                if redeclared {
+                    self.diagnostics.push(semantic_err!(stmt.span, "Redeclaration of function `{}`", data.name));
+                }
+                let params: Vec<DataType> = data.params.iter().map(|p| p.ty).collect();
+                self.check_signature(&params, data.result, &stmt.span);
+                if let Some(entry) = data.entry {
+                    if data.name == "main" {
+                        self.diagnostics.push(semantic_err!(stmt.span, "`main` can't be a `#[{entry}]` function"));
+                    } else if !params.is_empty() || data.result.is_some() {
+                        self.diagnostics.push(semantic_err!(stmt.span, "`#[{entry}]` function `{}` can't take parameters or return a value", data.name));
+                    }
                 }
 
                 let mut new_scope = Scope {
@@ -126,79 +532,153 @@ impl<'a> Analyzer<'a> {
                     parent: scope_idx
                 };
 
-                self.sp2 = if data.has_result { 1 } else { 0 };
+                self.sp2 = data.result.map_or(0, |ty| self.size_of(ty));
                 for p in &data.params {
-                    new_scope.items.insert(p, Type::Var(self.sp2));
-                    self.sp2 += 1;
+                    new_scope.items.insert(p.name, Type::Var(self.sp2, p.ty));
+                    self.sp2 += self.size_of(p.ty);
                 }
                 self.sp2 += 2;
 
                 let scope = self.scopes.len();
                 self.scopes.push(new_scope);
                 let flags = Flags {
-                    in_loop:    false,
-                    has_result: data.has_result
+                    in_loop: false,
+                    result:  data.result
                 };
 
                 let mut local_count = self.sp2;
-                for stmt in &data.body {
-                    self.analyze_stmt(stmt, scope, &flags);
-                }
+                self.analyze_stmts(&data.body, scope, &flags);
                 local_count = self.sp2 - local_count;
+                if let Some(ty) = data.result {
+                    if !always_returns(&data.body) {
+                        self.diagnostics.push(semantic_err!(stmt.span, "Missing return: function `{}` must return a `{ty}` on every path", data.name));
+                    }
+                }
 
-                self.scopes[scope_idx].items.insert(data.name, Type::FnDecl(FnDeclInfo {
-                    has_result:  data.has_result,
-                    param_count: data.params.len(),
-                    local_count
-                }));
+                if !redeclared {
+                    self.scopes[scope_idx].items.insert(data.name, Type::FnDecl(FnDeclInfo {
+                        result: data.result,
+                        params,
+                        local_count,
+                        span: stmt.span.clone(),
+                    }));
+                }
             },
 
-            StmtKind::VarDecl(name) => {
+            StmtKind::VarDecl(name, ty) => self.declare_var(name, *ty, &stmt.span, scope_idx),
+
+            StmtKind::VarDeclAssign { name, ty, expr } => {
+                // the type is inferred from the value when omitted
+                let ty = self.analyze_value(expr, *ty, scope_idx).unwrap_or(DataType::Int);
+                self.declare_var(name, ty, &stmt.span, scope_idx);
+            },
+
+            StmtKind::StructDecl { name, fields } => {
                 if self.scopes[scope_idx].items.contains_key(name) {
-                    semantic_err!(stmt.loc, "Redeclaration of variable `{name}`");
+                    self.diagnostics.push(semantic_err!(stmt.span, "Redeclaration of struct `{name}`"));
+                    return;
+                }
+                if fields.is_empty() {
+                    self.diagnostics.push(semantic_err!(stmt.span, "Struct `{name}` has no fields"));
+                }
+
+                let mut info = StructInfo { fields: Vec::with_capacity(fields.len()), size: 0 };
+                for field in fields {
+                    self.check_type(field.ty, &stmt.span);
+                    if let DataType::Array(..) = field.ty {
+                        self.diagnostics.push(semantic_err!(stmt.span, "Field `{}` can't be an array", field.name));
+                    }
+                    if info.fields.iter().any(|f| f.name == field.name) {
+                        self.diagnostics.push(semantic_err!(stmt.span, "Redeclaration of field `{}`", field.name));
+                    }
+                    info.fields.push(Field { name: field.name.to_string(), ty: field.ty, offset: info.size });
+                    info.size += self.size_of(field.ty);
                 }
-                self.scopes[scope_idx].items.insert(name, Type::Var(self.sp2));
-                self.sp2 += 1;
+                self.scopes[scope_idx].items.insert(name, Type::Struct(info));
             },
 
-            StmtKind::VarDeclAssign { name, expr } => {
+            StmtKind::ConstDecl { name, expr } => {
+                let ty = self.analyze_expr(expr, scope_idx).unwrap_or(DataType::Int);
                 if self.scopes[scope_idx].items.contains_key(name) {
-                    semantic_err!(stmt.loc, "Redeclaration of variable `{name}`");
+                    self.diagnostics.push(semantic_err!(stmt.span, "Redeclaration of constant `{name}`"));
+                    return;
                 }
-                self.analyze_expr(expr, scope_idx);
-                self.scopes[scope_idx].items.insert(name, Type::Var(self.sp2));
-                self.sp2 += 1;
+                self.scopes[scope_idx].items.insert(name, Type::Const(ty));
+            },
+
+            StmtKind::VarAssign { name, .. } | StmtKind::CompoundAssign { name, .. } if self.is_const(name, scope_idx) => {
+                self.diagnostics.push(semantic_err!(stmt.span, "Cannot assign to constant `{name}`"));
             },
 
             StmtKind::VarAssign { name, expr } => {
-                if !matches!(self.get_type_global(name, scope_idx), Some(Type::Var(_))) {
-                    semantic_err!(stmt.loc, "Variable `{name}` is not found");
+                match self.get_var_global(name, scope_idx) {
+                    Some(ty) => { self.analyze_value(expr, Some(ty), scope_idx); },
+                    None => {
+                        self.diagnostics.push(semantic_err!(stmt.span, "Variable `{name}` is not found"));
+                        self.analyze_expr(expr, scope_idx);
+                    },
                 }
-                self.analyze_expr(expr, scope_idx);
             },
 
-            StmtKind::FnCall { name, args } => {
-                if let Some(Type::FnDecl(data)) = self.get_type_global(name, scope_idx){
-                    if args.len() != data.param_count {
-                        semantic_err!(
-                            stmt.loc, "Function `{}` accepts only {} parameters",
-                            name, data.param_count
-                        );
-                    }
-                } else {
-                    semantic_err!(stmt.loc, "Function `{name}` is not found");
+            StmtKind::CompoundAssign { name, op: BinOpKind::Add, expr } if self.get_var_global(name, scope_idx) == Some(DataType::Str) => {
+                self.expect_text(expr, scope_idx);
+            },
+
+            StmtKind::CompoundAssign { name, op, expr } => {
+                match self.get_var_global(name, scope_idx) {
+                    Some(DataType::Int) => {},
+                    Some(ty) => {
+                        self.diagnostics.push(semantic_err!(stmt.span, "Operator `{op}=` expects `int`, found `{ty}`"));
+                    },
+                    None => {
+                        self.diagnostics.push(semantic_err!(stmt.span, "Variable `{name}` is not found"));
+                    },
                 }
+                self.expect_type(expr, DataType::Int, scope_idx);
+            },
 
-                for arg in args {
-                    self.analyze_expr(arg, scope_idx);
+            StmtKind::FieldAssign { name, fields, expr } => {
+                let ty = match self.get_var_global(name, scope_idx) {
+                    Some(ty) => self.field_type(ty, fields, &stmt.span),
+                    None => {
+                        self.diagnostics.push(semantic_err!(stmt.span, "Variable `{name}` is not found"));
+                        None
+                    },
+                };
+                match ty {
+                    Some(ty) => self.expect_type(expr, ty, scope_idx),
+                    None => { self.analyze_expr(expr, scope_idx); },
+                }
+            },
+
+            StmtKind::IndexAssign { name, index, expr } => {
+                match self.analyze_index(name, index, &stmt.span, scope_idx) {
+                    Some(ty) => self.expect_type(expr, ty, scope_idx),
+                    None => { self.analyze_expr(expr, scope_idx); },
+                }
+            },
+
+            StmtKind::FnCall { name, args } => {
+                let Some(info) = self.get_fn_global(name, scope_idx) else {
+                    self.diagnostics.push(semantic_err!(stmt.span, "Function `{name}` is not found"));
+                    for arg in args { self.analyze_expr(arg, scope_idx); }
+                    return;
+                };
+
+                if args.len() != info.params.len() {
+                    self.diagnostics.push(semantic_err!(
+                        stmt.span, "Function `{}` accepts only {} parameters",
+                        name, info.params.len()
+                    ).with_note(Some(info.span.clone()), "function declared here"));
                 }
+                self.analyze_args(args, &info, scope_idx);
             },
 
             StmtKind::If { cond, then, elzeifs, elze } => {
-                self.analyze_expr(cond, scope_idx);
+                self.expect_type(cond, DataType::Bool, scope_idx);
                 self.analyze_block(then, scope_idx, flags);
                 for elzeif in elzeifs {
-                    self.analyze_expr(&elzeif.cond, scope_idx);
+                    self.expect_type(&elzeif.cond, DataType::Bool, scope_idx);
                     self.analyze_block(&elzeif.then, scope_idx, flags);
                 }
                 self.analyze_block(elze, scope_idx, flags);
@@ -206,15 +686,35 @@ impl<'a> Analyzer<'a> {
 
             StmtKind::BuilinFnCall { name, arg } => {
                 match *name {
-                    "cmd" => {},
-                    "log" => {
-                        if !matches!(self.get_type_global(arg, scope_idx), Some(Type::Var(_))) {
-                            semantic_err!(stmt.loc, "Varible `{arg}` is not found");
+                    "cmd" => {
+                        for part in cmd_parts(arg) {
+                            let CmdPart::Var(var) = part else { continue; };
+                            match self.analyze_var_path(var, &stmt.span, scope_idx) {
+                                Some(DataType::Array(..)) => {
+                                    self.diagnostics.push(semantic_err!(stmt.span, "Array `{var}` can only be indexed"));
+                                },
+                                Some(ty @ DataType::Struct(_)) => {
+                                    self.diagnostics.push(semantic_err!(stmt.span, "`{var}` of type `{ty}` can't be used in `@cmd`"));
+                                },
+                                _ => {},
+                            }
                         }
                     },
-
                     _ => {
-                        semantic_err!(stmt.loc, "Builtin function `{name}` doesn't exist");
+                        self.diagnostics.push(semantic_err!(stmt.span, "Builtin function `{name}` doesn't exist"));
+                    }
+                }
+            },
+
+            StmtKind::Log { format, args, .. } => {
+                self.check_str(format, &stmt.span);
+                let placeholders = format.matches("{}").count();
+                if args.len() != placeholders {
+                    self.diagnostics.push(semantic_err!(stmt.span, "The format has {placeholders} placeholders, but {} values are given", args.len()));
+                }
+                for arg in args {
+                    if let Some(ty @ DataType::Struct(_)) = self.analyze_expr(arg, scope_idx) {
+                        self.diagnostics.push(semantic_err!(arg.span, "Values of `{ty}` can't be logged"));
                     }
                 }
             },
@@ -224,50 +724,424 @@ impl<'a> Analyzer<'a> {
                 let for_scope = self.scopes.len()-1;
                 let for_flags = Flags {
                     in_loop: true,
-                    has_result: flags.has_result
+                    result:  flags.result
                 };
 
                 if let Some(s) = init { self.analyze_stmt(s, for_scope, &for_flags); }
-                if let Some(e) = cond { self.analyze_expr(e, for_scope); }
+                if let Some(e) = cond { self.expect_type(e, DataType::Bool, for_scope); }
                 if let Some(s) = post { self.analyze_stmt(s, for_scope, &for_flags); }
 
-                for stmt in body {
-                    self.analyze_stmt(stmt, for_scope, &for_flags);
-                }
+                self.analyze_stmts(body, for_scope, &for_flags);
+            },
+
+            StmtKind::While { cond, body } => {
+                self.expect_type(cond, DataType::Bool, scope_idx);
+                self.analyze_block(body, scope_idx, &Flags { in_loop: true, result: flags.result });
+            },
+
+            StmtKind::Loop(body) => {
+                self.analyze_block(body, scope_idx, &Flags { in_loop: true, result: flags.result });
             },
 
             StmtKind::Break => {
                 if !flags.in_loop {
-                    semantic_err!(stmt.loc, "`break` is not in a loop");
+                    self.diagnostics.push(semantic_err!(stmt.span, "`break` is not in a loop"));
                 }
             },
 
+            StmtKind::Yield => {},
+            StmtKind::Sleep(ticks) => self.expect_type(ticks, DataType::Int, scope_idx),
+            StmtKind::Print(expr) => self.expect_text(expr, scope_idx),
+
             StmtKind::Continue => {
                 if !flags.in_loop {
-                    semantic_err!(stmt.loc, "`continue` is not in a loop");
+                    self.diagnostics.push(semantic_err!(stmt.span, "`continue` is not in a loop"));
                 }
             },
 
             StmtKind::Return => {
-                if flags.has_result {
-                    semantic_err!(stmt.loc, "The function must return value");
+                if flags.result.is_some() {
+                    self.diagnostics.push(semantic_err!(stmt.span, "The function must return value"));
                 }
             },
 
             StmtKind::ReturnVal(expr) => {
-                if !flags.has_result {
-                    semantic_err!(stmt.loc, "The function mustn't return value");
+                match flags.result {
+                    Some(ty) => self.expect_type(expr, ty, scope_idx),
+                    None => {
+                        self.diagnostics.push(semantic_err!(stmt.span, "The function mustn't return value"));
+                        self.analyze_expr(expr, scope_idx);
+                    },
                 }
-                self.analyze_expr(expr, scope_idx);
             },
         }
     }
 
+    fn declare_var(&mut self, name: Name<'a>, ty: DataType, span: &Span, scope_idx: ScopeIdx) {
+        if self.scopes[scope_idx].items.contains_key(name) {
+            self.diagnostics.push(semantic_err!(span, "Redeclaration of variable `{name}`"));
+            return;
+        }
+        self.check_type(ty, span);
+        let size = self.size_of(ty);
+        if scope_idx == 0 {
+            self.scopes[scope_idx].items.insert(name, Type::Var(self.globals, ty));
+            self.globals += size;
+        } else {
+            self.scopes[scope_idx].items.insert(name, Type::Var(self.sp2, ty));
+            self.sp2 += size;
+        }
+    }
+
+    fn analyze_stmts(&mut self, stmts: &'a [Stmt<'a>], scope: ScopeIdx, flags: &Flags) {
+        let mut jumped = false;
+        for stmt in stmts {
+            if jumped {
+                self.diagnostics.push(semantic_warn!(stmt.span, "Unreachable statement"));
+                jumped = false;
+            }
+
+            self.analyze_stmt(stmt, scope, flags);
+            if matches!(stmt.kind, StmtKind::Return | StmtKind::ReturnVal(_) | StmtKind::Break | StmtKind::Continue) {
+                jumped = true;
+            }
+        }
+    }
+
     fn analyze_block(&mut self, block: &'a Block<'a>, scope: ScopeIdx, flags: &Flags) {
         self.scopes.push(Scope { items: HashMap::new(), parent: scope });
         let curr_scope = self.scopes.len()-1;
-        for stmt in block {
-            self.analyze_stmt(stmt, curr_scope, flags);
-        }
+        self.analyze_stmts(block, curr_scope, flags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn analyze(src: &'static str) -> Vec<String> {
+        let mut lexer = Lexer::new(src.as_bytes(), 0);
+        let ast = parse(&mut lexer).unwrap();
+        let (_, diagnostics) = Analyzer::analyze(&ast);
+        diagnostics.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn multiple_errors() {
+        let diagnostics = analyze("
+            fn add(a, b) int {
+                return a + c
+            }
+
+            fn main() {
+                x := add(1)
+                y = x + z
+                foo()
+            }
+        ");
+
+        assert_eq!(diagnostics, [
+            "ERROR:3:28: SemanticError: Variable `c` is not found",
+            "ERROR:7:22: SemanticError: Function `add`'s arguments are incorrect",
+            "ERROR:8:17: SemanticError: Variable `y` is not found",
+            "ERROR:8:25: SemanticError: Variable `z` is not found",
+            "ERROR:9:17: SemanticError: Function `foo` is not found",
+        ]);
+    }
+
+    #[test]
+    fn type_errors() {
+        let diagnostics = analyze("
+            fn add(a, b) int { return a + b }
+            fn isPos(x) bool { return x }
+
+            fn main() {
+                if add(1, 2) {}
+                x := 1 < 2 + 3 < 4
+                flag: bool
+                flag = 3
+                y := add(flag, 1)
+                z := -flag
+                while x == 1 {}
+                ok: bool = !flag && x
+                flag += 1
+            }
+        ");
+
+        assert_eq!(diagnostics, [
+            "ERROR:3:39: SemanticError: Mismatched types: expected `bool`, found `int`",
+            "ERROR:6:20: SemanticError: Mismatched types: expected `bool`, found `int`",
+            "ERROR:7:22: SemanticError: Mismatched types: expected `int`, found `bool`",
+            "ERROR:9:24: SemanticError: Mismatched types: expected `bool`, found `int`",
+            "ERROR:10:26: SemanticError: Mismatched types: expected `int`, found `bool`",
+            "ERROR:11:23: SemanticError: Mismatched types: expected `int`, found `bool`",
+            "ERROR:12:28: SemanticError: Mismatched types: expected `bool`, found `int`",
+            "ERROR:14:17: SemanticError: Operator `+=` expects `int`, found `bool`",
+        ]);
+    }
+
+    #[test]
+    fn signatures() {
+        let diagnostics = analyze("
+            extern setblock(x: int, y: int, z: int)
+            extern toggle(int, on: bool)
+            extern getblock(int, int, int) -> int
+
+            fn dist(x: int, y: int) -> int { return x + y }
+            fn pick(c: bool, a, b) -> int {
+                if c { return a }
+                return b
+            }
+
+            fn main() {
+                d := dist(1, true)
+                setblock(1, 2, d)
+                toggle(1, 2)
+                ok: bool = pick(d > 1, 1, 2)
+            }
+        ");
+
+        assert_eq!(diagnostics, [
+            "ERROR:13:30: SemanticError: Mismatched types: expected `int`, found `bool`",
+            "ERROR:15:27: SemanticError: Mismatched types: expected `bool`, found `int`",
+            "ERROR:16:28: SemanticError: Mismatched types: expected `bool`, found `int`",
+        ]);
+    }
+
+    #[test]
+    fn constants() {
+        let diagnostics = analyze("
+            const N = 4
+            const N = 5
+            fn main() {
+                N = 1
+                N++
+                @log \"{}\" N
+                b: bool = N
+            }
+        ");
+        assert_eq!(diagnostics, [
+            "ERROR:3:13: SemanticError: Redeclaration of constant `N`",
+            "ERROR:5:17: SemanticError: Cannot assign to constant `N`",
+            "ERROR:6:17: SemanticError: Cannot assign to constant `N`",
+            "ERROR:8:27: SemanticError: Mismatched types: expected `bool`, found `int`",
+        ]);
+    }
+
+    #[test]
+    fn arrays() {
+        let diagnostics = analyze("
+            fn first(a: [int; 2]) -> int { return 0 }
+            fn main() {
+                arr: [int; 3] = [1, 2]
+                flags := [true, 1]
+                x := arr
+                arr[3] = 1
+                arr[0] = true
+                n := 5
+                n[0] = 1
+                y := [1, 2] == arr
+                @log \"{}\" arr
+            }
+        ");
+        assert_eq!(diagnostics, [
+            "ERROR:2:13: SemanticError: Arrays can't be passed to or returned from functions",
+            "ERROR:4:33: SemanticError: Mismatched types: expected `[int; 3]`, found `[int; 2]`",
+            "ERROR:5:33: SemanticError: Mismatched types: expected `bool`, found `int`",
+            "ERROR:6:22: SemanticError: Array `arr` can only be indexed",
+            "ERROR:7:21: SemanticError: Index 3 is out of bounds for `arr` of length 3",
+            "ERROR:8:26: SemanticError: Mismatched types: expected `int`, found `bool`",
+            "ERROR:10:17: SemanticError: Cannot index `n` of type `int`",
+            "ERROR:11:22: SemanticError: Array literals are only allowed as the value of an array variable",
+            "ERROR:11:32: SemanticError: Array `arr` can only be indexed",
+            "ERROR:12:27: SemanticError: Array `arr` can only be indexed",
+        ]);
+    }
+
+    #[test]
+    fn structs() {
+        let diagnostics = analyze("
+            struct Pos { x: int, y: int }
+            struct Pos { z: int }
+            struct Box { min: Pos, max: Vec, items: [int; 2] }
+            extern center() -> Pos
+            fn main() {
+                p := Pos { x: 1, z: 2, x: 3 }
+                b: bool = p.y
+                p.w = 1
+                n := 1
+                n.x = 2
+                if p == p {}
+                @log \"{}\" p
+            }
+        ");
+        assert_eq!(diagnostics, [
+            "ERROR:3:13: SemanticError: Redeclaration of struct `Pos`",
+            "ERROR:4:13: SemanticError: Struct `Vec` is not found",
+            "ERROR:4:13: SemanticError: Field `items` can't be an array",
+            "ERROR:5:13: SemanticError: Extern functions can't return `Pos`",
+            "ERROR:7:37: SemanticError: Struct `Pos` has no field `z`",
+            "ERROR:7:43: SemanticError: Field `x` is set twice",
+            "ERROR:7:22: SemanticError: Field `y` of `Pos` is not set",
+            "ERROR:8:27: SemanticError: Mismatched types: expected `bool`, found `int`",
+            "ERROR:9:17: SemanticError: Struct `Pos` has no field `w`",
+            "ERROR:11:17: SemanticError: Type `int` has no field `x`",
+            "ERROR:12:20: SemanticError: Values of `Pos` can't be compared",
+            "ERROR:13:27: SemanticError: Values of `Pos` can't be logged",
+        ]);
+    }
+
+    #[test]
+    fn strings() {
+        let diagnostics = analyze("
+            extern name() -> str
+            fn greet(who: str) -> str { return \"hi \" + who }
+            fn main() {
+                s := greet(\"bob\") + 1
+                t := \"on: \" + true
+                n: int = 1 + \"a\"
+                if s == t {}
+                s += 2
+                s -= 1
+                @log \"{}\" s
+                @print false
+                @cmd \"say {s}\"
+                @print \"a\\\\b\"
+                @cmd \"say a\\\\b\"
+            }
+        ");
+        assert_eq!(diagnostics, [
+            "ERROR:2:13: SemanticError: Extern functions can't return `str`",
+            "ERROR:6:31: SemanticError: Values of `bool` can't be joined to a `str`",
+            "ERROR:7:26: SemanticError: Mismatched types: expected `int`, found `str`",
+            "ERROR:8:20: SemanticError: Values of `str` can't be compared",
+            "ERROR:10:17: SemanticError: Operator `-=` expects `int`, found `str`",
+            "ERROR:12:24: SemanticError: Values of `bool` can't be joined to a `str`",
+            "ERROR:14:24: SemanticError: Strings can't contain `\\`",
+        ]);
+    }
+
+    #[test]
+    fn logging() {
+        let diagnostics = analyze("
+            fn main() {
+                x := 1
+                # the message is the text, not the variable
+                @log \"x\"
+                @debug \"{} and {}\" x
+                @warn \"{}\" x, x
+                @error \"{}\" undefined
+                @info \"x={} ok={}\" x, x > 0
+            }
+        ");
+        assert_eq!(diagnostics, [
+            "ERROR:6:17: SemanticError: The format has 2 placeholders, but 1 values are given",
+            "ERROR:7:17: SemanticError: The format has 1 placeholders, but 2 values are given",
+            "ERROR:8:29: SemanticError: Variable `undefined` is not found",
+        ]);
+    }
+
+    #[test]
+    fn arity_note() {
+        let src = "fn add(a, b) int { return a + b }\nfn main() {\n    x := add(1)\n}";
+        let mut lexer = Lexer::new(src.as_bytes(), 0);
+        let ast = parse(&mut lexer).unwrap();
+        let (_, diagnostics) = Analyzer::analyze(&ast);
+
+        assert_eq!(diagnostics[0].render("test.luba", src), "\
+ERROR: SemanticError: Function `add`'s arguments are incorrect
+ --> test.luba:3:10
+  |
+3 |     x := add(1)
+  |          ^^^^^^
+note: function declared here
+ --> test.luba:1:1
+  |
+1 | fn add(a, b) int { return a + b }
+  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+");
+    }
+
+    #[test]
+    fn entry_functions() {
+        let diagnostics = analyze("
+            #[load] fn init() {}
+            #[tick] fn update(n: int) {}
+            #[tick] fn count() -> int { return 1 }
+            #[load] fn main() {}
+        ");
+        assert_eq!(diagnostics, [
+            "ERROR:3:13: SemanticError: `#[tick]` function `update` can't take parameters or return a value",
+            "ERROR:4:13: SemanticError: `#[tick]` function `count` can't take parameters or return a value",
+            "ERROR:5:13: SemanticError: `main` can't be a `#[load]` function",
+        ]);
+
+        assert!(analyze("#[tick] fn update() {}").is_empty());
+    }
+
+    #[test]
+    fn missing_return() {
+        let diagnostics = analyze("
+            fn empty() int { }
+            fn sign(n: int) -> int {
+                if n > 0 { return 1 } else if n < 0 { return -1 }
+            }
+            fn clamp(n: int) -> int {
+                if n > 9 { return 9 } else { return n }
+            }
+            fn first() -> int {
+                loop {
+                    for ;; { break }
+                    return 1
+                }
+            }
+            fn escape() -> bool {
+                while true {
+                    if first() == 1 { break }
+                }
+            }
+            fn main() {}
+        ");
+        assert_eq!(diagnostics, [
+            "ERROR:2:13: SemanticError: Missing return: function `empty` must return a `int` on every path",
+            "ERROR:3:13: SemanticError: Missing return: function `sign` must return a `int` on every path",
+            "ERROR:15:13: SemanticError: Missing return: function `escape` must return a `bool` on every path",
+        ]);
+    }
+
+    #[test]
+    fn forward_and_recursive_calls() {
+        let diagnostics = analyze("
+            fn main() {
+                x := fact(5) + twice(1)
+                give(x)
+            }
+            fn fact(n: int) -> int {
+                if n <= 1 { return 1 }
+                return n * fact(n - 1)
+            }
+            fn twice(n: int) -> int { return n * 2 }
+            extern give(n: int)
+            fn twice() {}
+        ");
+        assert_eq!(diagnostics, ["ERROR:12:13: SemanticError: Redeclaration of function `twice`"]);
+    }
+
+    #[test]
+    fn unreachable_and_missing_main() {
+        let diagnostics = analyze("
+            fn foo() {
+                for ;; {
+                    break
+                    x := 1
+                }
+            }
+        ");
+
+        assert_eq!(diagnostics, [
+            "WARNING:5:21: SemanticWarning: Unreachable statement",
+            "ERROR: SemanticError: Function `main` is not found",
+        ]);
     }
 }
@@ -0,0 +1,1157 @@
+use std::{collections::{BTreeMap, HashMap}, fmt};
+
+use super::{runtime_err, exit_failure, parser::LogLevel};
+
+// Headless implementation of the redvm datapack. It reads the file produced
+// by `emitter::emit` and executes it the same way `redvm:run` and
+// `redvm:loop` do inside a minecraft world: at most `ipt` instructions per
+// game tick, and `sleep` skips the given number of ticks. Programs with
+// `#[tick]` functions are invoked at the tick entry like `<ns>:tick` does,
+// for the given number of game ticks.
+
+const INST_PREFIX: &str = "data modify storage redvm insts append value '";
+
+pub const DEFAULT_MAX_STACK: i32 = 1024;
+pub const DEFAULT_INSTS_PER_TICK: i32 = 256;
+pub const DEFAULT_TICKS: i32 = 20;
+pub const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Output {
+    Log(LogLevel, String),
+    Cmd(String),
+    Extern { name: String, args: Vec<String> },
+    Print(String),
+    Error(String),
+}
+
+#[derive(Default, Debug)]
+struct Regs {
+    ip:  i32,
+    sp:  i32,
+    sp2: i32,
+}
+
+#[derive(Debug)]
+pub struct Vm {
+    insts:  Vec<String>,
+    regs:   Regs,
+    local:  HashMap<i32, i32>,
+    global: HashMap<i32, i32>,
+    // `storage redvm arrays`, `l<slot>` for locals and `g<index>` for globals
+    arrays: HashMap<String, Vec<i32>>,
+    // `storage redvm strings`, keyed like the arrays, values on the stack
+    // are under the slot they occupy
+    strings: HashMap<String, String>,
+    eargs:  BTreeMap<usize, String>,
+    eret:   i32,
+    output: Vec<Output>,
+    // values returned by extern functions, 0 for the others
+    extern_results: HashMap<String, i32>,
+    // macro functions called `with storage redvm eargs`
+    functions: HashMap<String, String>,
+    max_sp: i32,
+    ipt:    i32,
+    budget: i32,
+    sleep:  i32,
+    halted: bool,
+    running: bool,
+    // ip of the `#[tick]` functions stub and the game ticks to simulate
+    tick_entry: Option<i32>,
+    ticks:  i32,
+    // executed instructions, the vm halts at `max_steps` so endless
+    // programs don't hang the cli
+    steps:  u64,
+    max_steps: u64,
+}
+
+impl Regs {
+    fn get(&mut self, name: &str) -> &mut i32 {
+        match name {
+            "ip"  => &mut self.ip,
+            "sp"  => &mut self.sp,
+            "sp2" => &mut self.sp2,
+            _ => { runtime_err!("Register `{name}` doesn't exist"); }
+        }
+    }
+}
+
+impl Vm {
+    pub fn load(src: &str) -> Self {
+        let mut insts = Vec::new();
+        for (row, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            match line.strip_prefix(INST_PREFIX).and_then(|l| l.strip_suffix('\'')) {
+                Some(inst) => insts.push(Self::unescape(inst)),
+                None => { runtime_err!("{}: Unsupported line `{line}`", row+1); }
+            }
+        }
+
+        Self {
+            insts,
+            regs:   Regs::default(),
+            local:  HashMap::new(),
+            global: HashMap::new(),
+            arrays: HashMap::new(),
+            strings: HashMap::new(),
+            eargs:  BTreeMap::new(),
+            eret:   0,
+            output: Vec::new(),
+            extern_results: HashMap::new(),
+            functions: HashMap::new(),
+            max_sp: DEFAULT_MAX_STACK,
+            ipt:    DEFAULT_INSTS_PER_TICK,
+            budget: 0,
+            sleep:  0,
+            halted: false,
+            running: true,
+            tick_entry: None,
+            ticks:  DEFAULT_TICKS,
+            steps:  0,
+            max_steps: DEFAULT_MAX_STEPS,
+        }
+    }
+
+    // the instructions are single-quoted SNBT strings
+    fn unescape(inst: &str) -> String {
+        let mut text = String::with_capacity(inst.len());
+        let mut chars = inst.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => text.extend(chars.next()),
+                '\'' => { runtime_err!("Unescaped quote in `{inst}`"); },
+                c => text.push(c),
+            }
+        }
+        text
+    }
+
+    pub fn with_max_stack(mut self, max_sp: i32) -> Self {
+        self.max_sp = max_sp;
+        self
+    }
+
+    pub fn with_insts_per_tick(mut self, ipt: i32) -> Self {
+        self.ipt = ipt;
+        self
+    }
+
+    pub fn with_extern_results(mut self, results: impl IntoIterator<Item = (String, i32)>) -> Self {
+        self.extern_results.extend(results);
+        self
+    }
+
+    pub fn with_tick_entry(mut self, ip: Option<usize>) -> Self {
+        self.tick_entry = ip.map(|ip| ip as i32);
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn with_ticks(mut self, ticks: i32) -> Self {
+        self.ticks = ticks;
+        self
+    }
+
+    pub fn with_functions(mut self, functions: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.functions.extend(functions);
+        self
+    }
+
+    pub fn run(mut self) -> Vec<Output> {
+        let Some(entry) = self.tick_entry else {
+            while self.tick() {}
+            return self.output;
+        };
+
+        for _ in 0..self.ticks {
+            if self.halted { break; }
+            self.invoke(entry);
+            self.tick();
+        }
+        self.output
+    }
+
+    // `redvm:invoke`, starts the program at `ip` unless the vm is busy
+    pub fn invoke(&mut self, ip: i32) -> bool {
+        if self.running || self.halted { return false; }
+        self.regs = Regs { ip, sp: 0, sp2: 0 };
+        self.sleep = 0;
+        self.running = true;
+        true
+    }
+
+    // `redvm:tick`, returns false once the program is finished
+    pub fn tick(&mut self) -> bool {
+        if self.sleep > 0 {
+            self.sleep -= 1;
+            if self.sleep > 0 { return true; }
+        }
+
+        self.budget = self.ipt;
+        while self.budget > 0 {
+            if !self.step() { return false; }
+            self.budget -= 1;
+        }
+        true
+    }
+
+    // returns false when the vm runs off the end of `insts` or halts
+    pub fn step(&mut self) -> bool {
+        if self.halted || !self.running { return false; }
+        let Some(inst) = usize::try_from(self.regs.ip).ok()
+            .and_then(|ip| self.insts.get(ip))
+            .cloned() else {
+            self.running = false;
+            return false;
+        };
+
+        self.regs.ip += 1;
+        if self.steps == self.max_steps {
+            self.halt(&format!("step limit of {} reached", self.max_steps));
+            return false;
+        }
+        self.steps += 1;
+        self.run_cmd(&inst);
+        true
+    }
+
+    // returns the result of the command like `execute store result` sees it
+    fn run_cmd(&mut self, cmd: &str) -> i32 {
+        let words: Vec<&str> = cmd.split(' ').collect();
+        match words.as_slice() {
+            ["function", inst, "with", "storage", "redvm", "eret"] if inst.starts_with("redvm:insts/") => {
+                self.run_inst(&inst["redvm:insts/".len()..], &format!("{{_:{}}}", self.eret))
+            },
+
+            ["function", inst, args @ ..] if inst.starts_with("redvm:insts/") => {
+                self.run_inst(&inst["redvm:insts/".len()..], &args.join(" "))
+            },
+
+            // `<ns>:extern/<name>`, the namespace of the program isn't known
+            ["function", ext, "with", "storage", "redvm", "eargs"] if ext.contains(":extern/") => {
+                let name = ext.split_once(":extern/").unwrap().1.to_string();
+                let args = self.eargs.values().cloned().collect();
+                let result = *self.extern_results.get(&name).unwrap_or(&0);
+                self.output.push(Output::Extern { name, args });
+                result
+            },
+
+            ["function", name, "with", "storage", "redvm", "eargs"] if self.functions.contains_key(*name) => {
+                let body = self.functions[*name].clone();
+                let mut result = 0;
+                for line in body.lines() {
+                    let line = match line.strip_prefix('$') {
+                        Some(line) => self.eargs.iter().fold(line.to_string(), |line, (key, val)| {
+                            line.replace(&format!("$({key})"), val)
+                        }),
+                        None => line.to_string(),
+                    };
+                    result = self.run_cmd(&line);
+                }
+                result
+            },
+
+            ["scoreboard", "players", op @ ("add" | "remove" | "set"), reg, "redvm.regs", n] => {
+                let n = Self::parse_num(n);
+                let reg = self.regs.get(reg);
+                match *op {
+                    "add"    => *reg = reg.wrapping_add(n),
+                    "remove" => *reg = reg.wrapping_sub(n),
+                    _        => *reg = n,
+                }
+                *reg
+            },
+
+            ["scoreboard", "players", "operation", dst, "redvm.regs", "=", src, "redvm.regs"] => {
+                let val = *self.regs.get(src);
+                *self.regs.get(dst) = val;
+                val
+            },
+
+            ["execute", "store", "result", "storage", "redvm", path, "int", "1", "run", rest @ ..] => {
+                let val = self.run_cmd(&rest.join(" "));
+                if *path == "eret._" {
+                    self.eret = val;
+                    return val;
+                }
+
+                let Some(idx) = path.strip_prefix("eargs.").and_then(|i| i.parse().ok()) else {
+                    runtime_err!("Unsupported storage path `{path}`");
+                };
+                self.eargs.insert(idx, val.to_string());
+                val
+            },
+
+            _ => {
+                self.output.push(Output::Cmd(cmd.to_string()));
+                1
+            }
+        }
+    }
+
+    fn run_inst(&mut self, name: &str, args: &str) -> i32 {
+        // the same checks as the first command of `redvm:insts/*`
+        let (pops, pushes) = match name {
+            "const" | "get_local" | "get_global" | "get_reg" | "call" => (0, 1),
+            "str_const" | "get_str_local" | "get_str_global" => (0, 1),
+            "set_local" | "set_global" | "set_reg" | "jmp_if" | "sleep" => (1, 0),
+            "set_str_local" | "set_str_global" | "to_str" | "print" | "log" => (1, 0),
+            "neg" | "not" | "bnot" | "op_local" | "op_global" | "get_elem_local" | "get_elem_global" => (1, 0),
+            "set_elem_local" | "set_elem_global" => (2, 0),
+            "add" | "sub" | "mul" | "div" | "mod" | "eq" | "ne" | "gt" | "ge" | "lt" | "le" | "and" | "or" | "str_cat" => (2, 1),
+            _ => (0, 0),
+        };
+        if self.regs.sp < pops {
+            return self.halt("stack underflow");
+        }
+        if pushes > 0 && self.regs.sp >= self.max_sp {
+            return self.halt("stack overflow");
+        }
+
+        let arg = args.strip_prefix("{_:").and_then(|a| a.strip_suffix('}'));
+        match name {
+            "const" => {
+                let n = Self::parse_num(Self::expect_arg(name, arg));
+                self.push(n);
+            },
+
+            "get_local" => {
+                let val = self.local(Self::parse_num(Self::expect_arg(name, arg)));
+                self.push(val);
+            },
+
+            "set_local" => {
+                let idx = self.regs.sp2 + Self::parse_num(Self::expect_arg(name, arg));
+                let val = self.pop();
+                self.local.insert(idx, val);
+            },
+
+            "see_local" => {
+                return self.local(Self::parse_num(Self::expect_arg(name, arg)));
+            },
+
+            "get_global" => {
+                let val = self.global(Self::parse_num(Self::expect_arg(name, arg)));
+                self.push(val);
+            },
+
+            "set_global" => {
+                let idx = Self::parse_num(Self::expect_arg(name, arg));
+                let val = self.pop();
+                self.global.insert(idx, val);
+            },
+
+            "see_global" => {
+                return self.global(Self::parse_num(Self::expect_arg(name, arg)));
+            },
+
+            "op_local" | "op_global" => {
+                let Some((idx, op)) = arg.and_then(|a| a.split_once(",op:")) else {
+                    runtime_err!("Instruction `{name}` expects an index and an operation");
+                };
+                let idx = Self::parse_num(idx);
+                let val = self.pop();
+                let (vars, idx) = if name == "op_local" { (&mut self.local, self.regs.sp2 + idx) } else { (&mut self.global, idx) };
+                let var = vars.entry(idx).or_insert(0);
+                *var = match op.trim_matches('"') {
+                    "+=" => var.wrapping_add(val),
+                    "-=" => var.wrapping_sub(val),
+                    "*=" => var.wrapping_mul(val),
+                    "/=" => if val == 0 { *var } else { floor_div(*var, val) },
+                    "%=" => if val == 0 { *var } else { floor_mod(*var, val) },
+                    op => { runtime_err!("Operation `{op}` doesn't exist"); }
+                };
+            },
+
+            "arr_local" | "arr_global" => {
+                let Some((idx, value)) = arg.and_then(|a| a.split_once(",value:")) else {
+                    runtime_err!("Instruction `{name}` expects an index and a value");
+                };
+                let key = self.storage_key(name, Self::parse_num(idx));
+                let value = value.trim_matches(|c| c == '[' || c == ']');
+                let value = if value.is_empty() { Vec::new() } else { value.split(',').map(Self::parse_num).collect() };
+                self.arrays.insert(key, value);
+            },
+
+            "get_elem_local" | "get_elem_global" | "set_elem_local" | "set_elem_global" => {
+                let Some((idx, len)) = arg.and_then(|a| a.split_once(",len:")) else {
+                    runtime_err!("Instruction `{name}` expects an index and a length");
+                };
+                let key = self.storage_key(name, Self::parse_num(idx));
+                let len = Self::parse_num(len);
+                let val = if name.starts_with("set") { Some(self.pop()) } else { None };
+                let i = self.pop();
+                if !(0..len).contains(&i) {
+                    return self.halt(&format!("index {i} is out of bounds for length {len}"));
+                }
+
+                let array = self.arrays.entry(key).or_default();
+                match val {
+                    Some(val) => if let Some(elem) = array.get_mut(i as usize) { *elem = val; },
+                    None => {
+                        let val = array.get(i as usize).copied().unwrap_or(0);
+                        self.push(val);
+                    },
+                }
+            },
+
+            "str_const" => {
+                let text = Self::expect_arg(name, arg).trim_matches('"').to_string();
+                self.strings.insert(format!("l{}", self.regs.sp), text);
+                self.regs.sp += 1;
+            },
+
+            "get_str_local" | "get_str_global" => {
+                let text = self.string(&self.storage_key(name, Self::parse_num(Self::expect_arg(name, arg))));
+                self.strings.insert(format!("l{}", self.regs.sp), text);
+                self.regs.sp += 1;
+            },
+
+            "set_str_local" | "set_str_global" => {
+                let key = self.storage_key(name, Self::parse_num(Self::expect_arg(name, arg)));
+                self.regs.sp -= 1;
+                let text = self.string(&format!("l{}", self.regs.sp));
+                self.strings.insert(key, text);
+            },
+
+            "to_str" => {
+                let text = self.local.get(&(self.regs.sp - 1)).unwrap_or(&0).to_string();
+                self.strings.insert(format!("l{}", self.regs.sp - 1), text);
+            },
+
+            "str_cat" => {
+                self.regs.sp -= 1;
+                let rhs = self.string(&format!("l{}", self.regs.sp));
+                self.strings.entry(format!("l{}", self.regs.sp - 1)).or_default().push_str(&rhs);
+            },
+
+            "print" => {
+                self.regs.sp -= 1;
+                let text = self.string(&format!("l{}", self.regs.sp));
+                self.output.push(Output::Print(text));
+            },
+
+            "load_str_local" | "load_str_global" => {
+                let Some((idx, arg)) = arg.and_then(|a| a.split_once(",arg:")) else {
+                    runtime_err!("Instruction `{name}` expects an index and an argument");
+                };
+                let text = self.string(&self.storage_key(name, Self::parse_num(idx)));
+                self.eargs.insert(Self::parse_num(arg) as usize, text);
+            },
+
+            // `{_:"info",color:"white"}`, the color is only used by minecraft
+            "log" => {
+                let level = args.strip_prefix("{_:\"").and_then(|a| a.split_once('"'))
+                    .and_then(|(level, _)| LogLevel::from_name(level));
+                let Some(level) = level else {
+                    runtime_err!("Instruction `{name}` expects a log level");
+                };
+                self.regs.sp -= 1;
+                let text = self.string(&format!("l{}", self.regs.sp));
+                self.output.push(Output::Log(level, text));
+            },
+
+            "get_reg" => {
+                let val = *self.regs.get(Self::expect_arg(name, arg));
+                self.push(val);
+            },
+
+            "set_reg" => {
+                let val = self.pop();
+                *self.regs.get(Self::expect_arg(name, arg)) = val;
+            },
+
+            "jmp_if" => {
+                let addr = Self::parse_num(Self::expect_arg(name, arg));
+                if self.pop() >= 1 { self.regs.ip = addr; }
+            },
+
+            "call" => {
+                let addr = Self::parse_num(Self::expect_arg(name, arg));
+                self.push(self.regs.ip);
+                self.regs.ip = addr;
+            },
+
+            "sleep" => {
+                self.sleep = self.pop();
+                self.budget = 0;
+            },
+
+            "neg"  => self.unary_op(|a| a.wrapping_neg()),
+            "not"  => self.unary_op(|a| (a < 1) as i32),
+            "bnot" => self.unary_op(|a| !a),
+
+            "add" => self.bin_op(|a, b| a.wrapping_add(b)),
+            "sub" => self.bin_op(|a, b| a.wrapping_sub(b)),
+            "mul" => self.bin_op(|a, b| a.wrapping_mul(b)),
+            "div" => self.bin_op(|a, b| if b == 0 { a } else { floor_div(a, b) }),
+            "mod" => self.bin_op(|a, b| if b == 0 { a } else { floor_mod(a, b) }),
+            "eq"  => self.bin_op(|a, b| (a == b) as i32),
+            "ne"  => self.bin_op(|a, b| (a != b) as i32),
+            "gt"  => self.bin_op(|a, b| (a >  b) as i32),
+            "ge"  => self.bin_op(|a, b| (a >= b) as i32),
+            "lt"  => self.bin_op(|a, b| (a <  b) as i32),
+            "le"  => self.bin_op(|a, b| (a <= b) as i32),
+            "and" => self.bin_op(|a, b| (a >= 1 && b >= 1) as i32),
+            "or"  => self.bin_op(|a, b| (a.max(b) >= 1) as i32),
+
+            _ => { runtime_err!("ip {}: Instruction `{name}` doesn't exist", self.regs.ip-1); }
+        }
+
+        1
+    }
+
+    // `redvm:utils/error`
+    fn halt(&mut self, msg: &str) -> i32 {
+        self.regs.ip -= 1;
+        self.halted = true;
+        self.output.push(Output::Error(format!("{msg} at ip {}", self.regs.ip)));
+        0
+    }
+
+    fn bin_op(&mut self, op: impl Fn(i32, i32) -> i32) {
+        let rhs = self.pop();
+        let lhs = self.pop();
+        self.push(op(lhs, rhs));
+    }
+
+    fn unary_op(&mut self, op: impl Fn(i32) -> i32) {
+        let val = self.pop();
+        self.push(op(val));
+    }
+
+    fn push(&mut self, val: i32) {
+        self.local.insert(self.regs.sp, val);
+        self.regs.sp += 1;
+    }
+
+    fn pop(&mut self) -> i32 {
+        self.regs.sp -= 1;
+        *self.local.get(&self.regs.sp).unwrap_or(&0)
+    }
+
+    // unset scores are read as 0
+    fn local(&self, idx: i32) -> i32 {
+        *self.local.get(&(self.regs.sp2 + idx)).unwrap_or(&0)
+    }
+
+    fn storage_key(&self, inst: &str, idx: i32) -> String {
+        if inst.ends_with("_local") { format!("l{}", self.regs.sp2 + idx) } else { format!("g{idx}") }
+    }
+
+    // a missing string is read as empty
+    fn string(&self, key: &str) -> String {
+        self.strings.get(key).cloned().unwrap_or_default()
+    }
+
+    fn global(&self, idx: i32) -> i32 {
+        *self.global.get(&idx).unwrap_or(&0)
+    }
+
+    fn expect_arg<'b>(name: &str, arg: Option<&'b str>) -> &'b str {
+        arg.unwrap_or_else(|| {
+            runtime_err!("Instruction `{name}` expects an argument");
+        })
+    }
+
+    fn parse_num(text: &str) -> i32 {
+        text.parse().unwrap_or_else(|_| {
+            runtime_err!("Invalid 32-bit integer `{text}`");
+        })
+    }
+}
+
+// scoreboard operations round towards negative infinity
+pub fn floor_div(a: i32, b: i32) -> i32 {
+    let q = a.wrapping_div(b);
+    if a.wrapping_rem(b) != 0 && (a < 0) != (b < 0) { q - 1 } else { q }
+}
+
+pub fn floor_mod(a: i32, b: i32) -> i32 {
+    a.wrapping_sub(floor_div(a, b).wrapping_mul(b))
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Output::Log(level, text) => write!(f, "{}: {text}", level.to_string().to_uppercase()),
+            Output::Cmd(cmd) => write!(f, "CMD: {cmd}"),
+            Output::Print(text) => write!(f, "PRINT: {text}"),
+            Output::Error(msg) => write!(f, "ERROR: {msg}"),
+            Output::Extern { name, args } => {
+                write!(f, "EXTERN: {name}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 { write!(f, ", ")?; }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, module::Strings, parser::{self, LogLevel}, semantic, compiler, emitter, fold};
+
+    fn load(src: &'static str) -> Vm {
+        load_level(src, Some(LogLevel::Debug))
+    }
+
+    fn load_level(src: &'static str, log_level: Option<LogLevel>) -> Vm {
+        let (text, macros, tick_ip) = emit(src, log_level);
+        Vm::load(&text).with_functions(macros).with_tick_entry(tick_ip)
+    }
+
+    // the emitted program, its macro functions and the tick entry
+    fn emit(src: &'static str, log_level: Option<LogLevel>) -> (String, Vec<(String, String)>, Option<usize>) {
+        let mut strings = Strings::default();
+        let mut lexer = Lexer::new(src.as_bytes(), 0);
+        let mut ast = parser::parse(&mut lexer).unwrap();
+        let folded = fold::fold(&mut ast, &mut strings);
+        assert!(folded.is_empty(), "{folded:?}");
+        let (scopes, diagnostics) = semantic::Analyzer::analyze(&ast);
+        assert!(!diagnostics.iter().any(|d| d.is_error()), "{diagnostics:?}");
+        let program = compiler::compile(&ast, scopes, log_level);
+        let mut out = std::io::Cursor::new(Vec::new());
+        emitter::emit(&mut out, &program, "luba");
+        (String::from_utf8(out.into_inner()).unwrap(), emitter::macros(&program, "luba"), emitter::tick_ip(&program))
+    }
+
+    fn run(src: &'static str) -> Vec<Output> {
+        load(src).run()
+    }
+
+    fn log(val: impl ToString) -> Output {
+        Output::Log(LogLevel::Info, val.to_string())
+    }
+
+    #[test]
+    fn arithmetic() {
+        let output = run("
+            fn main() {
+                a := 7
+                b := a*a - 9
+                c := b/-3
+                d := -7 % 3
+                @log \"{}\" b
+                @log \"{}\" c
+                @log \"{}\" d
+            }
+        ");
+        assert_eq!(output, [log(40), log(-14), log(2)]);
+    }
+
+    #[test]
+    fn calls() {
+        let output = run("
+            fn add(a, b) int {
+                return a + b
+            }
+
+            fn sum(n) int {
+                s := 0
+                for i := 1; i <= n; i = i+1 {
+                    s = add(s, i)
+                }
+                return s
+            }
+
+            fn main() {
+                x := add(2, 3)
+                y := sum(10)
+                @log \"{}\" x
+                @log \"{}\" y
+            }
+        ");
+        assert_eq!(output, [log(5), log(55)]);
+    }
+
+    #[test]
+    fn control_flow() {
+        let output = run("
+            fn main() {
+                for i := 1; i <= 20; i = i+1 {
+                    if i % 15 == 0 {
+                        @cmd \"say FizzBuzz\"
+                    } else if i > 16 {
+                        @log \"{}\" i
+                        break
+                    }
+                }
+            }
+        ");
+        assert_eq!(output, [Output::Cmd("say FizzBuzz".to_string()), log(17)]);
+    }
+
+    #[test]
+    fn unary_operators() {
+        let output = run("
+            fn main() {
+                x := 5
+                a := -x * 2
+                b := !(x > 3) || !false && x == 5
+                c := ~x
+                d := 10 - 4 - 3
+                @log \"{}\" a
+                @log \"{}\" b
+                @log \"{}\" c
+                @log \"{}\" d
+            }
+        ");
+        assert_eq!(output, [log(-10), log(1), log(-6), log(3)]);
+    }
+
+    #[test]
+    fn short_circuit() {
+        let output = run("
+            fn check(x) bool {
+                @cmd \"say {x}\"
+                return x != 0
+            }
+
+            fn main() {
+                i := 0
+                a := i != 0 && 10 / i > 1
+                b := check(0) && check(1)
+                c := check(5) || check(2)
+                d := check(0) || check(-3) || false
+                @log \"{}\" a
+                @log \"{}\" b
+                @log \"{}\" c
+                @log \"{}\" d
+            }
+        ");
+        let says: Vec<Output> = ["0", "5", "0", "-3"].iter().map(|x| Output::Cmd(format!("say {x}"))).collect();
+        assert_eq!(output[..4], says);
+        assert_eq!(output[4..], [log(0), log(0), log(1), log(1)]);
+    }
+
+    #[test]
+    fn loops() {
+        let output = run("
+            fn main() {
+                n := 27
+                steps := 0
+                while n != 1 {
+                    if n % 2 == 0 { n = n / 2 } else { n = 3*n + 1 }
+                    steps = steps + 1
+                }
+                @log \"{}\" steps
+
+                odd := 0
+                for i := 0; i < 10; i = i+1 {
+                    if i % 2 == 0 { continue }
+                    odd = odd + 1
+                }
+                @log \"{}\" odd
+
+                k := 0
+                loop {
+                    k = k + 1
+                    if k < 5 { continue }
+                    break
+                }
+                @log \"{}\" k
+            }
+        ");
+        assert_eq!(output, [log(111), log(5), log(5)]);
+    }
+
+    #[test]
+    fn externs() {
+        let src = "
+            extern setblock(int, int, int)
+
+            fn main() {
+                setblock(1, -2, 3)
+            }
+        ";
+        let output = run(src);
+        assert_eq!(output, [Output::Extern { name: "setblock".to_string(), args: ["1", "-2", "3"].map(String::from).to_vec() }]);
+
+        // the same path as in the native backend
+        let (text, _, _) = emit(src, None);
+        assert!(text.contains("value 'function luba:extern/setblock with storage redvm eargs'"), "{text}");
+    }
+
+    #[test]
+    fn extern_results() {
+        let output = load("
+            extern getblock(x: int, y: int, z: int) -> int
+            extern isday() -> bool
+
+            fn main() {
+                if isday() {
+                    b := getblock(1, 2, 3) + 1
+                    @log \"{}\" b
+                }
+            }
+        ").with_extern_results([("getblock".to_string(), 41), ("isday".to_string(), 1)]).run();
+
+        assert_eq!(output, [
+            Output::Extern { name: "isday".to_string(), args: vec![] },
+            Output::Extern { name: "getblock".to_string(), args: ["1", "2", "3"].map(String::from).to_vec() },
+            log(42),
+        ]);
+    }
+
+    #[test]
+    fn cmd_interpolation() {
+        let output = run("
+            fn main() {
+                x := 4
+                y := x * 2
+                @cmd \"setblock {x} {y} {x} stone\"
+                @cmd \"summon pig ~ ~ ~ {NoAI:1b}\"
+            }
+        ");
+        assert_eq!(output, [
+            Output::Cmd("setblock 4 8 4 stone".to_string()),
+            Output::Cmd("summon pig ~ ~ ~ {NoAI:1b}".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn globals() {
+        let output = run("
+            count := 10
+            done: bool
+            step := count / 5
+
+            fn bump() {
+                count = count + step
+            }
+
+            fn main() {
+                @log \"{}\" count
+                bump()
+                bump()
+                @log \"{}\" count
+                @cmd \"scoreboard players set total obj {count}\"
+                done = true
+                if done { @log \"{}\" step }
+            }
+        ");
+        assert_eq!(output, [
+            log(10),
+            log(14),
+            Output::Cmd("scoreboard players set total obj 14".to_string()),
+            log(2),
+        ]);
+    }
+
+    #[test]
+    fn constants() {
+        let output = run("
+            const SIZE = 2 * 3
+            const DEBUG = SIZE > 4
+
+            fn main() {
+                const HALF = SIZE / 2
+                n := 0
+                for i := 0; i < SIZE; i = i+1 {
+                    n = n + HALF
+                }
+                @log \"{}\" n
+                if DEBUG { @cmd \"fill 0 0 0 {SIZE} {HALF} {SIZE} stone\" }
+            }
+        ");
+        assert_eq!(output, [log(18), Output::Cmd("fill 0 0 0 6 3 6 stone".to_string())]);
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let output = run("
+            total := 0
+
+            fn main() {
+                x := 7
+                x += 3
+                x *= 4
+                x -= 2
+                x /= -3
+                y := x
+                y %= 5
+                for i := 0; i < 4; i++ {
+                    total += i
+                }
+                total--
+                @log \"{}\" x
+                @log \"{}\" y
+                @log \"{}\" total
+            }
+        ");
+        assert_eq!(output, [log(-13), log(2), log(5)]);
+    }
+
+    #[test]
+    fn arrays() {
+        let output = run("
+            const N = 4
+            squares: [int; 4]
+            primes := [2, 3, 5, 7]
+
+            fn sum(n) -> int {
+                parts := [n, n*2, 0]
+                parts[2] = primes[0]
+                return parts[0] + parts[1] + parts[2]
+            }
+
+            fn main() {
+                for i := 0; i < N; i++ {
+                    squares[i] = i * i
+                }
+                flags: [bool; 2] = [true, primes[1] > 5]
+                x := squares[3] + primes[N-1]
+                y := sum(2) + sum(1)
+                if !flags[1] { @log \"{}\" x }
+                @log \"{}\" y
+            }
+        ");
+        assert_eq!(output, [log(16), log(13)]);
+    }
+
+    #[test]
+    fn structs() {
+        let output = run("
+            struct Pos { x: int, y: int, z: int }
+            struct Line { start: Pos, end: Pos }
+
+            extern setblock(p: Pos, solid: bool)
+
+            origin := Pos { x: 1, y: 64, z: -1 }
+
+            fn add(a: Pos, b: Pos) -> Pos {
+                return Pos { x: a.x + b.x, y: a.y + b.y, z: a.z + b.z }
+            }
+
+            fn width(l: Line) -> int {
+                return l.end.x - l.start.x
+            }
+
+            fn main() {
+                p := add(origin, Pos { z: 3, y: 1, x: 2 })
+                p.x = p.x * 10
+                line: Line
+                line.start = origin
+                line.end = p
+                w := width(line)
+                @log \"{}\" w
+                @log \"{}\" line.end.y
+                setblock(p, true)
+                @cmd \"tp {origin.x} {p.y} {line.end.z}\"
+            }
+        ");
+        assert_eq!(output, [
+            log(29),
+            log(65),
+            Output::Extern { name: "setblock".to_string(), args: ["30", "65", "2", "1"].map(String::from).to_vec() },
+            Output::Cmd("tp 1 65 2".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn strings() {
+        let output = run("
+            struct Item { name: str, count: int }
+
+            extern give(item: str, count: int)
+
+            const UNIT = \" blocks\"
+            title: str
+
+            fn describe(item: Item) -> str {
+                return item.name + \" x\" + item.count
+            }
+
+            fn main() {
+                @print title
+                title = \"Build\"
+                title += \": \"
+                n := 24
+                @print title + \"Built \" + n + UNIT + \" in \" + (n / 8) + \" ticks\"
+                item := Item { name: \"stone\", count: 2 + 1 }
+                s := describe(item)
+                @print s
+                @print 1 + 2
+                give(item.name, item.count)
+                @cmd \"give @p {item.name} {n}\"
+            }
+        ");
+        assert_eq!(output, [
+            Output::Print(String::new()),
+            Output::Print("Build: Built 24 blocks in 3 ticks".to_string()),
+            Output::Print("stone x3".to_string()),
+            Output::Print("3".to_string()),
+            Output::Extern { name: "give".to_string(), args: ["stone", "3"].map(String::from).to_vec() },
+            Output::Cmd("give @p stone 24".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn tick_functions() {
+        let output = load("
+            counter := 0
+
+            #[load] fn init() {
+                counter = 10
+                @log \"init {}\" counter
+            }
+
+            #[tick] fn update() {
+                counter += 1
+                if counter % 2 == 0 { @log \"{}\" counter }
+            }
+
+            fn main() { @log \"main\" }
+        ").with_ticks(5).run();
+        assert_eq!(output, [log("init 10"), log("main"), log(12), log(14)]);
+
+        // the vm is busy while the previous tick sleeps, so those ticks are skipped
+        let output = load("#[tick] fn slow() { @log \"tick\"\nsleep(2) }").with_ticks(8).run();
+        assert_eq!(output, [log("tick"), log("tick"), log("tick")]);
+    }
+
+    #[test]
+    fn quotes() {
+        let src = "
+            fn main() {
+                s := \"it's \" + 1
+                @print s
+                @cmd \"say it's \\o/\"
+            }
+        ";
+        let text = emit(src, None).0;
+        assert!(text.contains("append value 'function redvm:insts/str_const {_:\"it\\'s 1\"}'\n"));
+        assert!(text.contains("append value 'say it\\'s \\\\o/'\n"));
+        assert_eq!(run(src), [Output::Print("it's 1".to_string()), Output::Cmd("say it's \\o/".to_string())]);
+    }
+
+    #[test]
+    fn logging() {
+        let src = "
+            struct Pos { x: int, y: int }
+
+            fn main() {
+                pos := Pos { x: 3, y: -4 }
+                name := \"steve\"
+                @debug \"pos={},{}\" pos.x, pos.y
+                @log \"{} is at {}\" name, pos.x + pos.y
+                @warn \"{}{}\" pos.x > 0, 7
+                @error \"no values\"
+                @log \"name\"
+            }
+        ";
+        let output = run(src);
+        assert_eq!(output, [
+            Output::Log(LogLevel::Debug, "pos=3,-4".to_string()),
+            Output::Log(LogLevel::Info, "steve is at -1".to_string()),
+            Output::Log(LogLevel::Warn, "17".to_string()),
+            Output::Log(LogLevel::Error, "no values".to_string()),
+            log("name"),
+        ]);
+        assert_eq!(output[1].to_string(), "INFO: steve is at -1");
+
+        let output = load_level(src, Some(LogLevel::Warn)).run();
+        assert_eq!(output.iter().map(|o| o.to_string()).collect::<Vec<_>>(), ["WARN: 17", "ERROR: no values"]);
+        assert!(load_level(src, None).run().is_empty());
+    }
+
+    #[test]
+    fn array_bounds() {
+        let output = run("
+            fn main() {
+                arr: [int; 3]
+                i := 3
+                arr[i - 1] = 1
+                x := arr[i]
+            }
+        ");
+        assert_eq!(output, [Output::Error("index 3 is out of bounds for length 3 at ip 15".to_string())]);
+    }
+
+    #[test]
+    fn stack_checks() {
+        let mut lexer = Lexer::new(b"fn main() { x := 1 + (2 + (3 + 4)) }", 0);
+        let ast = parser::parse(&mut lexer).unwrap();
+        let (scopes, diagnostics) = semantic::Analyzer::analyze(&ast);
+        assert!(!diagnostics.iter().any(|d| d.is_error()), "{diagnostics:?}");
+        let mut out = Vec::new();
+        emitter::emit(&mut out, &compiler::compile(&ast, scopes, Some(LogLevel::Debug)), "luba");
+
+        let output = Vm::load(&String::from_utf8(out).unwrap()).with_max_stack(5).run();
+        assert_eq!(output, [Output::Error("stack overflow at ip 8".to_string())]);
+
+        let output = Vm::load(&format!("{INST_PREFIX}function redvm:insts/const {{_:1}}'\n{INST_PREFIX}function redvm:insts/add'")).run();
+        assert_eq!(output, [Output::Error("stack underflow at ip 1".to_string())]);
+    }
+
+    #[test]
+    fn runaway_recursion() {
+        let output = run("
+            fn main() {
+                @log \"{}\" countdown(3)
+                x := deeper(0)
+            }
+
+            fn countdown(n: int) -> int {
+                if n == 0 { return 0 }
+                return n + countdown(n - 1)
+            }
+
+            fn deeper(n: int) -> int {
+                return deeper(n + 1)
+            }
+        ");
+        assert_eq!(output, [log(6), Output::Error("stack overflow at ip 51".to_string())]);
+    }
+
+    #[test]
+    fn step_limit() {
+        let output = load("fn main() { loop {} }").with_max_steps(1000).run();
+        assert_eq!(output, [Output::Error("step limit of 1000 reached at ip 6".to_string())]);
+
+        let output = load("#[tick] fn update() { @log \"tick\" }").with_max_steps(12).with_ticks(5).run();
+        assert_eq!(output[0], log("tick"));
+        assert_eq!(output.last(), Some(&Output::Error("step limit of 12 reached at ip 1".to_string())));
+    }
+
+    #[test]
+    fn scoreboard_division() {
+        assert_eq!(floor_div(-7, 2), -4);
+        assert_eq!(floor_div(7, -2), -4);
+        assert_eq!(floor_mod(-7, 3), 2);
+        assert_eq!(floor_mod(7, -3), -2);
+        assert_eq!(floor_div(i32::MIN, -1), i32::MIN);
+    }
+
+    #[test]
+    fn ticks() {
+        let mut vm = load("
+            fn main() {
+                @cmd \"say a\"
+                sleep(3)
+                @cmd \"say b\"
+                yield
+                for i := 0; i < 100; i = i+1 {}
+                @cmd \"say c\"
+            }
+        ").with_insts_per_tick(64);
+
+        let mut ticks = Vec::new();
+        while vm.tick() {
+            ticks.push(vm.output.len());
+        }
+        assert_eq!(ticks[..5], [1, 1, 1, 2, 2]);
+        assert!(ticks.len() > 6, "{ticks:?}");
+        assert_eq!(vm.output.len(), 3);
+    }
+}